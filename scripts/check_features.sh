@@ -0,0 +1,8 @@
+#!/bin/sh
+# Check that the crate builds both with and without the GUI, and with every feature enabled.
+set -e
+cd "$(dirname "$0")/.."
+
+cargo check --all-targets
+cargo check --all-targets --no-default-features
+cargo check --all-targets --all-features
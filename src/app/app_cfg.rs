@@ -1,8 +1,14 @@
+use std::collections::HashSet;
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use vid_dup_finder_lib::*;
 
+use crate::app::{
+    app_fns::{DEFAULT_THUMB_FPS, DEFAULT_THUMB_FRAMES, DEFAULT_THUMB_SIZE},
+    KeeperPolicy, KeeperRules,
+};
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum ReportVerbosity {
     Quiet,
@@ -10,40 +16,234 @@ pub enum ReportVerbosity {
     Verbose,
 }
 
+//How to order the unique files printed by --search-unique.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UniqueSortKey {
+    Path,
+    Size,
+    Duration,
+    Resolution,
+    Bitrate,
+}
+
+//The stat used by --auto-resolve to choose which video in a group to keep.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AutoResolveMetric {
+    LargestRes,
+    LargestFile,
+    LargestPngsize,
+    HighestBitrate,
+}
+
+impl AutoResolveMetric {
+    pub fn label(self) -> &'static str {
+        match self {
+            AutoResolveMetric::LargestRes => "largest-res",
+            AutoResolveMetric::LargestFile => "largest-file",
+            AutoResolveMetric::LargestPngsize => "largest-pngsize",
+            AutoResolveMetric::HighestBitrate => "highest-bitrate",
+        }
+    }
+}
+
+//How to order groups of duplicates, with --sort.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GroupSortKey {
+    Similarity,
+    Size,
+    Path,
+    GroupSize,
+}
+
 #[derive(Debug, Clone)]
 pub struct OutputCfg {
     pub print_unique: bool,
     pub print_duplicates: bool,
+    pub sort_unique_by: Option<UniqueSortKey>,
     pub json_output: bool,
+    pub json_lines: bool,
+    pub json_rich: bool,
+    pub classify_renames: bool,
+    pub preserve_input_order: bool,
+    pub sort_groups_by: Option<GroupSortKey>,
+    pub ladder_order: bool,
+    pub max_groups: Option<usize>,
+    pub dir_summary: bool,
+    pub print_stats: bool,
+    pub relative_to: Option<PathBuf>,
     pub output_thumbs_dir: Option<PathBuf>,
+    pub output_file: Option<PathBuf>,
+    pub thumbs_concurrency: Option<usize>,
+    pub thumb_size: Option<u32>,
+    pub thumb_frames: Option<u32>,
+    pub thumb_fps: Option<String>,
 
     pub verbosity: ReportVerbosity,
 
     pub gui: bool,
+    pub tui: bool,
+    pub auto_resolve: Option<AutoResolveMetric>,
+    pub move_dups_to: Option<PathBuf>,
     pub gui_trash_path: Option<PathBuf>,
+    pub gui_archive_path: Option<PathBuf>,
+    //Only read by the GUI.
+    #[cfg_attr(not(all(target_family = "unix", feature = "gui")), allow(dead_code))]
+    pub player: Option<String>,
+    #[cfg_attr(not(all(target_family = "unix", feature = "gui")), allow(dead_code))]
+    pub file_manager: Option<String>,
+}
+
+impl OutputCfg {
+    //The form of a path to be printed in the results. Only paths inside --relative-to are shortened.
+    pub fn output_path<'a>(&self, path: &'a Path) -> &'a Path {
+        self.relative_to
+            .as_ref()
+            .and_then(|base| path.strip_prefix(base).ok())
+            .unwrap_or(path)
+    }
+
+    pub fn match_image_layout(&self) -> MatchImageLayout {
+        MatchImageLayout {
+            thumb_size: self.thumb_size.unwrap_or(DEFAULT_THUMB_SIZE),
+            num_frames: self.thumb_frames.unwrap_or(DEFAULT_THUMB_FRAMES),
+            fps: self
+                .thumb_fps
+                .clone()
+                .unwrap_or_else(|| DEFAULT_THUMB_FPS.to_string()),
+        }
+    }
+}
+
+//How frames are sampled from each video in a match image, and how large they are drawn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchImageLayout {
+    pub thumb_size: u32,
+    pub num_frames: u32,
+    pub fps: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RefSet {
+    pub name: String,
+    pub path: PathBuf,
 }
 
 #[derive(Debug, Clone)]
 pub struct DirCfg {
     pub cand_dirs: Vec<PathBuf>,
+    pub missing_cand_paths: Vec<PathBuf>,
     pub ref_dirs: Vec<PathBuf>,
+    pub ref_sets: Vec<RefSet>,
+    pub protect_dirs: Vec<PathBuf>,
+    pub keep_list: HashSet<PathBuf>,
     pub excl_dirs: Vec<PathBuf>,
+    pub excl_regexes: Vec<regex::Regex>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
     pub excl_exts: Vec<OsString>,
+    pub incl_exts: Option<Vec<String>>,
+    pub no_recurse: bool,
+    pub follow_symlinks: bool,
+}
+
+impl DirCfg {
+    //The name of the --ref-set containing the given path. If sets are nested, the innermost set is chosen.
+    pub fn ref_set_name(&self, path: &Path) -> Option<&str> {
+        self.ref_sets
+            .iter()
+            .filter(|ref_set| path.starts_with(&ref_set.path))
+            .max_by_key(|ref_set| ref_set.path.components().count())
+            .map(|ref_set| ref_set.name.as_str())
+    }
+
+    //The distinct names of all --ref-sets, in the order they were given.
+    pub fn ref_set_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = vec![];
+        for ref_set in &self.ref_sets {
+            if !names.contains(&ref_set.name.as_str()) {
+                names.push(&ref_set.name);
+            }
+        }
+        names
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AudioFilter {
+    Any,
+    Require,
+    Exclude,
+}
+
+#[derive(Debug, Clone)]
+pub struct CandFilterCfg {
+    pub audio: AudioFilter,
+    pub min_resolution: Option<(u32, u32)>,
 }
 
 #[derive(Debug, Clone)]
 pub struct CacheCfg {
     pub cache_path: Option<PathBuf>,
     pub no_update_cache: bool,
+    pub resume: bool,
+    pub prune: bool,
+    pub compact: bool,
+    pub discard_non_videos: bool,
+    pub flush_interval: Option<u32>,
+    pub wait_for_lock: bool,
+    pub invalidate_on_ffmpeg_change: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportCfg {
+    pub export_dir: PathBuf,
+    pub flatten: bool,
+    pub hardlink: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct AppCfg {
     pub cache_cfg: CacheCfg,
     pub dir_cfg: DirCfg,
+    pub cand_filter_cfg: CandFilterCfg,
 
     pub output_cfg: OutputCfg,
+    pub export_cfg: Option<ExportCfg>,
 
     pub update_cache_only: bool,
+    pub cache_plan: bool,
+    pub inspect: Option<PathBuf>,
+    pub dump_cache_json: Option<PathBuf>,
+    pub explain_match: Option<(PathBuf, PathBuf)>,
+    pub compare: Option<(PathBuf, PathBuf)>,
+    pub tune_tolerance: Option<PathBuf>,
+    pub ffmpeg_nice: Option<i32>,
+    pub threads: Option<usize>,
     pub tolerance: NormalizedTolerance,
+    pub auto_tolerance: bool,
+    pub robustness_check: Option<PathBuf>,
+    pub multi_ref: bool,
+    pub prefilter_duration: Option<f64>,
+    pub min_group_size: Option<usize>,
+    pub transitive: bool,
+    pub verify_identical: bool,
+    pub exact_first: bool,
+    pub affirm_audio: bool,
+    pub prefer_subtitles: bool,
+    pub require_keeper_readable: bool,
+    pub results_file: Option<PathBuf>,
+    pub baseline: Option<PathBuf>,
+    pub dry_run: bool,
+}
+
+impl AppCfg {
+    pub fn keeper_rules(&self, policy: KeeperPolicy) -> KeeperRules {
+        KeeperRules {
+            policy,
+            protect_dirs: self.dir_cfg.protect_dirs.clone(),
+            keep_list: self.dir_cfg.keep_list.clone(),
+            prefer_subtitles: self.prefer_subtitles,
+            require_readable: self.require_keeper_readable,
+        }
+    }
 }
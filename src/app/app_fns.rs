@@ -1,8 +1,9 @@
 use std::{
-    collections::{hash_map::RandomState, HashSet},
+    collections::{hash_map::RandomState, BTreeSet, HashSet},
     error::Error,
     ffi::OsString,
-    io::BufWriter,
+    fs::File,
+    io::{BufWriter, Write},
     path::{Path, PathBuf},
 };
 
@@ -56,11 +57,60 @@ fn run_app_inner(cfg: &AppCfg) -> Result<Vec<AppError>, AppError> {
         return Err(AppError::FfmpegNotFound);
     }
 
+    //ffmpeg is started by the library crates, so its priority can't be set directly. Instead lower the priority
+    //of this process, which every ffmpeg/ffprobe process inherits. That would also slow down the GUI, so the
+    //priority is left alone if the GUI is going to be shown.
+    if let Some(niceness) = cfg.ffmpeg_nice {
+        if cfg.output_cfg.gui && !cfg.update_cache_only {
+            warn!("--ffmpeg-nice is ignored with --gui, as it would lower the priority of the GUI too");
+        } else {
+            lower_process_priority(niceness);
+        }
+    }
+
+    //The library crates hash and search with rayon, which uses the thread pool that it is called from. So with
+    //--threads, hashing and searching are run inside a pool of that size.
+    let thread_pool = match cfg.threads {
+        Some(threads) => match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+            Ok(pool) => Some(pool),
+            Err(e) => {
+                warn!(
+                    "Failed to limit the number of threads to {}: {}",
+                    threads, e
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    //Results can depend slightly on the version of ffmpeg, so record it for diagnostics.
+    let ffmpeg_version = ffmpeg_version::ffmpeg_version();
+    match &ffmpeg_version {
+        Some(version) => {
+            info!("Using {}", version);
+            ffmpeg_version::check_ffmpeg_version(version);
+        }
+        None => debug!("Could not determine ffmpeg version"),
+    }
+
+    if let Some(src_path) = &cfg.robustness_check {
+        return robustness_check::run_robustness_check(src_path, cfg.tolerance);
+    }
+
     //shorten some long variable names
     let cand_dirs = &cfg.dir_cfg.cand_dirs;
     let ref_dirs = &cfg.dir_cfg.ref_dirs;
-    let excl_dirs = &cfg.dir_cfg.excl_dirs;
+    let excl_dirs = &excl_dirs_with_trash_dirs(cfg);
     let excl_exts = &cfg.dir_cfg.excl_exts;
+    let path_filter = PathFilter::from_cfg(&cfg.dir_cfg);
+
+    nonfatal_errs.extend(
+        cfg.dir_cfg
+            .missing_cand_paths
+            .iter()
+            .map(|path| AppError::FilesFromPathNotFoundError(path.clone())),
+    );
 
     // Check that there are no shared paths in refs and cands.
     for cand_path in cand_dirs {
@@ -77,29 +127,243 @@ fn run_app_inner(cfg: &AppCfg) -> Result<Vec<AppError>, AppError> {
     let (cand_excls, ref_excls) =
         resolve_shadowing_paths_of_cands_and_refs(cand_dirs, ref_dirs, excl_dirs);
 
+    let cache_path = cfg
+        .cache_cfg
+        .cache_path
+        .as_ref()
+        .ok_or(AppError::NoCachePath)?;
+
+    //The cache is only saved after all files have been hashed, so if it can't be written then fail now
+    //instead of after hours of work.
+    //Read-only instances never save the cache, so only instances which write to it need to lock it.
+    let _cache_lock = if !cfg.cache_cfg.no_update_cache {
+        check_cache_dir_writable(cache_path)?;
+        Some(cache_lock::lock_cache(
+            cache_path,
+            cfg.cache_cfg.wait_for_lock,
+        )?)
+    } else {
+        None
+    };
+    if let Some(version) = &ffmpeg_version {
+        ffmpeg_version::check_cache_ffmpeg_version(
+            cache_path,
+            version,
+            cfg.cache_cfg.invalidate_on_ffmpeg_change,
+            cfg.cache_cfg.no_update_cache,
+        )?;
+    }
+
     //load up existing hashes from disk. If no-cache-mode is specified, then set the save threshold of the cache
     //to a very high number
-    let cache_save_threshold = 100;
-    let cache = VideoHashFilesystemCache::new(
-        cache_save_threshold,
-        cfg.cache_cfg.cache_path.as_ref().unwrap().clone(),
-    )?;
+    let cache_save_threshold = cfg
+        .cache_cfg
+        .flush_interval
+        .unwrap_or(DEFAULT_CACHE_FLUSH_INTERVAL);
+    let cache = VideoHashFilesystemCache::new(cache_save_threshold, cache_path.clone())?;
 
-    // Update the cache file with all videos specified by --files and --with-refs
-    if !cfg.cache_cfg.no_update_cache {
-        update_hash_cache(
+    if cfg.cache_cfg.prune {
+        prune_cache::prune_cache(&cache, cfg.dry_run)?;
+        return Ok(nonfatal_errs);
+    }
+
+    if cfg.cache_cfg.compact {
+        prune_cache::compact_cache(
+            &cache,
+            cache_path,
+            cfg.cache_cfg.discard_non_videos,
+            cfg.dry_run,
+        )?;
+        return Ok(nonfatal_errs);
+    }
+
+    //If the user only wants to know what an update would do, then compare the filesystem with the cache and stop.
+    if cfg.cache_plan {
+        let (cands, refs) = project_cands_and_refs_using_fs(
             cand_dirs,
             &cand_excls,
             excl_exts,
             ref_dirs,
             &ref_excls,
+            &path_filter,
             &mut nonfatal_errs,
-            &cache,
         )?;
+        let on_disk = cands
+            .projected_files()
+            .union(refs.projected_files())
+            .cloned()
+            .collect::<HashSet<PathBuf, RandomState>>();
+
+        //Cached files that would be removed by an update are those inside --files or --with-refs
+        //that are no longer on disk.
+        let all_cached_paths = cache
+            .all_cached_paths()
+            .into_iter()
+            .collect::<HashSet<PathBuf, RandomState>>();
+        let (cached_cands, cached_refs) = project_cands_and_refs_using_list(
+            cand_dirs,
+            &cand_excls,
+            excl_exts,
+            ref_dirs,
+            &ref_excls,
+            &path_filter,
+            &all_cached_paths,
+        )?;
+        let cached = cached_cands
+            .projected_files()
+            .union(cached_refs.projected_files())
+            .cloned()
+            .collect::<HashSet<PathBuf, RandomState>>();
+
+        cache_plan::print_cache_plan(&on_disk, &cached, cache_path);
+        return Ok(nonfatal_errs);
+    }
+
+    if let Some(inspect_path) = &cfg.inspect {
+        inspect::print_inspection(inspect_path, &cache)?;
+        return Ok(nonfatal_errs);
+    }
+
+    if let Some(output_path) = &cfg.dump_cache_json {
+        inspect::dump_cache_json(&cache, output_path, cfg.dry_run)?;
+        return Ok(nonfatal_errs);
+    }
+
+    if let Some((path_a, path_b)) = &cfg.explain_match {
+        hash_components::print_match_explanation(path_a, path_b, &cache)?;
+        return Ok(nonfatal_errs);
+    }
+
+    //Comparing two files only needs those two files to be hashed, so skip enumerating the search paths.
+    if let Some((path_a, path_b)) = &cfg.compare {
+        for src_path in [path_a, path_b].iter() {
+            if !src_path.exists() {
+                return Err(AppError::CandPathNotFoundError(src_path.to_path_buf()));
+            }
+        }
+        if !cfg.cache_cfg.no_update_cache {
+            hash_files(&[path_a, path_b], excl_exts, &mut nonfatal_errs, &cache)?;
+        }
+        hash_components::print_comparison(path_a, path_b, &cache)?;
+        return Ok(nonfatal_errs);
+    }
+
+    if let Some(labels_path) = &cfg.tune_tolerance {
+        let pairs = tune_tolerance::load_labels(labels_path)?;
+        if !cfg.cache_cfg.no_update_cache {
+            let labeled_paths = pairs
+                .iter()
+                .flat_map(|pair| [&pair.path_a, &pair.path_b])
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            hash_files(&labeled_paths, excl_exts, &mut nonfatal_errs, &cache)?;
+        }
+        tune_tolerance::print_tolerance_tuning(&pairs, &cache);
+        return Ok(nonfatal_errs);
+    }
+
+    //If the user already has search results, then resolve them without searching again.
+    if let Some(results_file) = &cfg.results_file {
+        let thunks = results_file::load_results(results_file)?
+            .iter()
+            .map(|group| {
+                ResolutionThunk::from_paths(
+                    group.reference.as_deref(),
+                    group.duplicates.iter().map(PathBuf::as_path),
+                    &cache,
+                    cfg,
+                )
+            })
+            .collect();
+        run_resolver(thunks, cfg)?;
+        return Ok(nonfatal_errs);
+    }
+
+    let print_stats =
+        cfg.output_cfg.print_stats || cfg.output_cfg.verbosity == ReportVerbosity::Verbose;
+    let mut run_stats = RunStats::default();
+
+    // Update the cache file with all videos specified by --files and --with-refs
+    if !cfg.cache_cfg.no_update_cache {
+        interrupt::forget_interrupted_files(&cache, cache_path)?;
+
+        let saved_enumeration = if cfg.cache_cfg.resume {
+            resume::load_enumeration(cache_path, cand_dirs, ref_dirs)
+        } else {
+            None
+        };
+
+        let (cands, refs) = match saved_enumeration {
+            Some(saved_paths) => {
+                info!(
+                    "Resuming cache update of {} files found by a previous run",
+                    saved_paths.len()
+                );
+                project_cands_and_refs_using_list(
+                    cand_dirs,
+                    &cand_excls,
+                    excl_exts,
+                    ref_dirs,
+                    &ref_excls,
+                    &path_filter,
+                    &saved_paths,
+                )?
+            }
+            None => {
+                let (cands, refs) = project_cands_and_refs_using_fs(
+                    cand_dirs,
+                    &cand_excls,
+                    excl_exts,
+                    ref_dirs,
+                    &ref_excls,
+                    &path_filter,
+                    &mut nonfatal_errs,
+                )?;
+                resume::save_enumeration(
+                    cache_path,
+                    cand_dirs,
+                    ref_dirs,
+                    cands.projected_files().iter().chain(refs.projected_files()),
+                );
+                (cands, refs)
+            }
+        };
+
+        let previously_cached = if print_stats {
+            cache.all_cached_paths().into_iter().collect()
+        } else {
+            HashSet::default()
+        };
+        let num_errs_before_update = nonfatal_errs.len();
+
+        interrupt::save_cache_on_interrupt(&cache, cache_path, || {
+            install(thread_pool.as_ref(), || {
+                update_hash_cache(&cands, &refs, &mut nonfatal_errs, &cache)
+            })
+        })?;
+        resume::remove_enumeration(cache_path);
+
+        if print_stats {
+            let enumerated = cands
+                .projected_files()
+                .union(refs.projected_files())
+                .cloned()
+                .collect::<HashSet<PathBuf, RandomState>>();
+            run_stats.record_update(
+                &enumerated,
+                &previously_cached,
+                &nonfatal_errs[num_errs_before_update..],
+                &cache,
+            );
+        }
     }
 
     //if the app was only invoked to update the cache, then we're done at this point.
     if cfg.update_cache_only {
+        if print_stats {
+            run_stats.print();
+        }
         return Ok(nonfatal_errs);
     }
 
@@ -114,83 +378,435 @@ fn run_app_inner(cfg: &AppCfg) -> Result<Vec<AppError>, AppError> {
     let mut cand_projection = FileProjection::new(cand_dirs, cand_excls, excl_exts.clone())
         .map_err(AppError::from_cand_exclusion_error)?;
     cand_projection.project_using_list(&all_hash_paths);
-    let cand_paths = cand_projection.projected_files();
-    let cand_hashes = cand_paths
+    path_filter.apply_to_cands(&mut cand_projection);
+    let cand_paths = cand_projection
+        .projected_files()
         .iter()
-        .map(|cand_path| cache.fetch(cand_path).unwrap())
-        .collect::<Vec<_>>();
+        .filter(|cand_path| passes_cand_filters(&cfg.cand_filter_cfg, &cache, cand_path))
+        .cloned()
+        .collect::<HashSet<PathBuf, RandomState>>();
 
     let mut ref_projection = FileProjection::new(ref_dirs, ref_excls, excl_exts.clone())
         .map_err(AppError::from_ref_exclusion_error)?;
     ref_projection.project_using_list(&all_hash_paths);
+    path_filter.apply_to_refs(&mut ref_projection);
     let ref_paths = ref_projection.projected_files();
-    let ref_hashes = ref_paths
-        .iter()
-        .map(|ref_path| cache.fetch(ref_path).unwrap())
-        .collect::<Vec<_>>();
 
-    let matchset = obtain_thunks(cfg, cand_hashes, ref_hashes);
+    //If the user asked for help choosing a tolerance, then sweep over several tolerances instead of searching.
+    if cfg.auto_tolerance {
+        tolerance_sweep::print_tolerance_sweep(
+            fetch_hashes(&cache, cand_paths.iter()),
+            fetch_hashes(&cache, ref_paths.iter()),
+        );
+        return Ok(nonfatal_errs);
+    }
 
-    if cfg.output_cfg.gui {
-        #[cfg(all(target_family = "unix", feature = "gui"))]
-        {
-            let thunks = matchset
-                .into_iter()
-                .map(|match_group| {
-                    ResolutionThunk::from_matchgroup(
-                        &match_group,
-                        &cache,
-                        &cfg.output_cfg.gui_trash_path,
-                    )
-                })
-                .collect();
-            run_gui(thunks)?;
+    let mut cand_paths_vec = cand_paths.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+
+    //Files with identical contents don't need to be searched to know that they are duplicates, so with
+    //--exact-first they are grouped now and all but one copy of each is left out of the search.
+    let exact_groups = if cfg.exact_first {
+        let ref_paths = ref_paths.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+        let exact_groups = exact_dups::find_exact_groups(&cand_paths_vec, &ref_paths);
+        cand_paths_vec.retain(|cand_path| !exact_groups.excluded.contains(*cand_path));
+        exact_groups.groups
+    } else {
+        vec![]
+    };
+
+    let mut matchset = install(thread_pool.as_ref(), || {
+        if cfg.dir_cfg.ref_sets.is_empty() {
+            let ref_paths = ref_paths.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+            obtain_thunks(cfg, &cache, &cand_paths_vec, &ref_paths)
+        } else {
+            //Search against each named reference set separately, so that a candidate can be reported
+            //against each set that it matches.
+            let mut matchset = vec![];
+            for ref_set_name in cfg.dir_cfg.ref_set_names() {
+                let set_paths = ref_paths
+                    .iter()
+                    .filter(|ref_path| cfg.dir_cfg.ref_set_name(ref_path) == Some(ref_set_name))
+                    .map(PathBuf::as_path)
+                    .collect::<Vec<_>>();
+
+                if set_paths.is_empty() {
+                    warn!(
+                        "No reference files were found for --ref-set {}. No results will be returned for this set.",
+                        ref_set_name
+                    );
+                    continue;
+                }
+
+                matchset.extend(obtain_thunks(cfg, &cache, &cand_paths_vec, &set_paths));
+            }
+            matchset
+        }
+    });
+    if cfg.transitive {
+        matchset = search_output::merge_transitive(matchset);
+    }
+    if cfg.affirm_audio {
+        matchset = search_output::affirm_audio(
+            matchset,
+            &cache,
+            cache_path,
+            cfg.cache_cfg.no_update_cache,
+        );
+    }
+    matchset.splice(0..0, exact_groups);
+    //Members of groups which are too small are not reported as duplicates, so they are counted as unique.
+    if let Some(min_group_size) = cfg.min_group_size {
+        matchset.retain(|group| search_output::group_paths(group).count() >= min_group_size);
+    }
+    if cfg.output_cfg.preserve_input_order {
+        search_output::sort_by_input_order(&mut matchset, cand_dirs);
+    }
+    if let Some(key) = cfg.output_cfg.sort_groups_by {
+        search_output::sort_groups(&mut matchset, key, &cache);
+    }
+    if cfg.verify_identical {
+        let (verified, unverified) = search_output::verify_identical(matchset, &cache);
+        if !unverified.is_empty() {
+            warn!("Videos look identical to others but their contents differ, so they are not reported as duplicates:");
+            for src_path in &unverified {
+                warn!("    {}", src_path.display());
+            }
         }
+        matchset = verified;
+    }
+    if let Some(baseline_file) = &cfg.baseline {
+        let baseline_paths = results_file::load_baseline_paths(baseline_file)?;
+        matchset.retain(|group| {
+            search_output::group_paths(group).any(|src_path| !baseline_paths.contains(src_path))
+        });
+    }
+    if let Some(max_groups) = cfg.output_cfg.max_groups {
+        if matchset.len() > max_groups {
+            warn!(
+                "Search results were truncated to the first {} of {} groups",
+                max_groups,
+                matchset.len()
+            );
+            matchset.truncate(max_groups);
+        }
+    }
+
+    if print_stats {
+        run_stats.record_search(cand_paths.len() + ref_paths.len(), &matchset, &cache);
+    }
+
+    if cfg.output_cfg.gui || cfg.output_cfg.tui || cfg.output_cfg.auto_resolve.is_some() {
+        let thunks = matchset
+            .into_iter()
+            .map(|match_group| ResolutionThunk::from_matchgroup(&match_group, &cache, cfg))
+            .collect();
+        run_resolver(thunks, cfg)?;
     } else if let Some(output_thumbs_dir) = &cfg.output_cfg.output_thumbs_dir {
         use rayon::prelude::*;
 
-        let font =
-            rusttype::Font::try_from_bytes(include_bytes!("font/NotoSans-Regular.ttf")).unwrap();
+        let font = match_image_font();
+        let layout = cfg.output_cfg.match_image_layout();
+
+        let groups = matchset
+            .iter()
+            .map(|match_group| {
+                (
+                    match_group.reference(),
+                    search_output::ordered_duplicates(match_group, &cache, &cfg.output_cfg),
+                )
+            })
+            .collect::<Vec<_>>();
 
-        matchset
-            .par_iter()
-            .enumerate()
-            .for_each(|(i, match_group)| {
-                let output_path = output_thumbs_dir.join(format!("{}.png", i));
+        let write_images = || {
+            groups
+                .par_iter()
+                .enumerate()
+                .for_each(|(i, (reference, duplicates))| {
+                    let output_path = output_thumbs_dir.join(format!("{}.png", i));
+                    if cfg.dry_run {
+                        println!("Would write match image to {}", output_path.display());
+                        return;
+                    }
 
-                let reference = match_group.reference();
-                let duplicates = match_group.duplicates();
+                    write_image(*reference, duplicates, &output_path, &font, &layout);
+                })
+        };
 
-                write_image(reference, duplicates, &output_path, &font);
-            });
+        //Every image being written holds all of its frames in memory, so with many groups the number of
+        //images written at once may need to be limited. The filenames don't depend on the order that images
+        //are written in.
+        match cfg.output_cfg.thumbs_concurrency {
+            Some(concurrency) => match rayon::ThreadPoolBuilder::new()
+                .num_threads(concurrency)
+                .build()
+            {
+                Ok(pool) => pool.install(write_images),
+                Err(e) => {
+                    warn!(
+                        "Failed to limit the number of match images written at once to {}: {}",
+                        concurrency, e
+                    );
+                    write_images();
+                }
+            },
+            None => write_images(),
+        }
     } else {
-        let search_output = SearchOutput::new(matchset);
+        let mut search_output = SearchOutput::new(matchset);
+        if cfg.output_cfg.classify_renames {
+            search_output.classify_renames(&cache);
+        }
+        if cfg.output_cfg.verbosity == ReportVerbosity::Verbose {
+            hash_components::log_component_histogram(&search_output, &cache);
+        }
 
         // The user may have unique hashes to be printed. Calculate that here.
         let dup_paths = search_output
             .dup_paths()
             .map(PathBuf::from)
             .collect::<HashSet<PathBuf, RandomState>>();
-        let unique_paths = cand_paths
+        let mut unique_paths = cand_paths
             .difference(&dup_paths)
             .map(|x| x.as_path())
             .collect::<Vec<_>>();
+        if let Some(key) = cfg.output_cfg.sort_unique_by {
+            search_output::sort_unique_paths(&mut unique_paths, key, &cache);
+        }
+
+        if let Some(export_cfg) = &cfg.export_cfg {
+            nonfatal_errs.extend(export::export_unique(
+                &search_output,
+                &unique_paths,
+                &cache,
+                &cfg.keeper_rules(KeeperPolicy::BestQuality),
+                export_cfg,
+                cfg.dry_run,
+            ));
+        }
+
+        let write_output = |out: &mut dyn Write| {
+            if cfg.output_cfg.dir_summary {
+                dir_summary::print_dir_summary(
+                    &search_output,
+                    &cache,
+                    &cfg.keeper_rules(KeeperPolicy::Largest),
+                    &cfg.output_cfg,
+                    out,
+                )
+            } else {
+                let report = SearchReport::new(&search_output, &unique_paths, &cache, cfg);
+                print_search_results(&report, &cache, &cfg.output_cfg, out)
+            }
+        };
+        match &cfg.output_cfg.output_file {
+            //With --dry-run the results that would have been written to the file are printed instead.
+            Some(output_file) if cfg.dry_run => {
+                println!("Would write search results to {}", output_file.display());
+                let mut out = BufWriter::new(std::io::stdout());
+                write_output(&mut out).map_err(AppError::OutputError)?;
+            }
+            Some(output_file) => {
+                let output_err = |e| AppError::OutputFileError(output_file.clone(), e);
+                let file = File::create(output_file).map_err(output_err)?;
+                let mut out = BufWriter::new(file);
+                write_output(&mut out).map_err(output_err)?;
+            }
+            None => {
+                let mut out = BufWriter::new(std::io::stdout());
+                write_output(&mut out).map_err(AppError::OutputError)?;
+            }
+        }
+    }
 
-        print_search_results(&search_output, &unique_paths, cfg);
+    if print_stats {
+        run_stats.print();
     }
 
     Ok(nonfatal_errs)
 }
 
+//Resolve groups of duplicates automatically or with the GUI if either was requested, or otherwise in the terminal.
+fn run_resolver(thunks: Vec<ResolutionThunk>, cfg: &AppCfg) -> Result<(), AppError> {
+    if let Some(metric) = cfg.output_cfg.auto_resolve {
+        auto_resolve::run_auto_resolve(thunks, metric);
+        return Ok(());
+    }
+
+    if cfg.output_cfg.gui {
+        #[cfg(all(target_family = "unix", feature = "gui"))]
+        return run_gui(
+            thunks,
+            cfg.output_cfg.thumb_size,
+            ExternalApps::from_cfg(&cfg.output_cfg),
+        );
+    }
+
+    tui::run_tui(thunks);
+    Ok(())
+}
+
+//Create the directory holding the cache if needed, and check that a file can be created in it. Fails with
+//CacheDirNotWritable otherwise.
+fn check_cache_dir_writable(cache_path: &Path) -> Result<(), AppError> {
+    let cache_dir = match cache_path.parent() {
+        Some(cache_dir) if !cache_dir.as_os_str().is_empty() => cache_dir,
+        _ => Path::new("."),
+    };
+
+    let not_writable = |e| AppError::CacheDirNotWritable(cache_dir.to_path_buf(), e);
+    std::fs::create_dir_all(cache_dir).map_err(not_writable)?;
+
+    let test_path = cache_dir.join(format!(".vid_dup_finder_write_test_{}", std::process::id()));
+    std::fs::write(&test_path, b"").map_err(not_writable)?;
+    std::fs::remove_file(&test_path).map_err(not_writable)?;
+
+    Ok(())
+}
+
+//Files which have already been moved into the trash (or --move-dups-to) should not be searched again (where they
+//would match the copies that were kept), so if the trash is inside --files or --with-refs then exclude it.
+fn excl_dirs_with_trash_dirs(cfg: &AppCfg) -> Vec<PathBuf> {
+    let trash_dirs = cfg
+        .output_cfg
+        .gui_trash_path
+        .iter()
+        .chain(cfg.output_cfg.move_dups_to.iter());
+
+    let mut excl_dirs = cfg.dir_cfg.excl_dirs.clone();
+    for trash_dir in trash_dirs {
+        let inside_search_dirs = cfg
+            .dir_cfg
+            .cand_dirs
+            .iter()
+            .chain(cfg.dir_cfg.ref_dirs.iter())
+            .any(|search_dir| trash_dir.starts_with(search_dir) && trash_dir != search_dir);
+
+        if inside_search_dirs && trash_dir.exists() && !excl_dirs.iter().any(|e| e == trash_dir) {
+            debug!("Excluding trash directory {}", trash_dir.display());
+            excl_dirs.push(trash_dir.to_path_buf());
+        }
+    }
+
+    excl_dirs
+}
+
+#[cfg(target_family = "unix")]
+fn lower_process_priority(niceness: i32) {
+    let ret = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) };
+    if ret != 0 {
+        warn!(
+            "Failed to lower process priority: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(target_family = "unix"))]
+fn lower_process_priority(_niceness: i32) {
+    warn!("--ffmpeg-nice is only supported on unix. Ignoring.");
+}
+
+//Filters on --files which can only be applied once the stats of each file are known. If stats are
+//unavailable then it is not known whether the file passes, so it is only kept when no filter is active.
+fn passes_cand_filters(
+    filter_cfg: &CandFilterCfg,
+    cache: &VideoHashFilesystemCache,
+    src_path: &Path,
+) -> bool {
+    let passes_audio = match filter_cfg.audio {
+        AudioFilter::Any => true,
+        AudioFilter::Require => cache
+            .fetch_stats(src_path)
+            .map(|stats| stats.has_audio())
+            .unwrap_or(false),
+        AudioFilter::Exclude => cache
+            .fetch_stats(src_path)
+            .map(|stats| !stats.has_audio())
+            .unwrap_or(false),
+    };
+
+    //Portrait videos are compared against the rotated minimum resolution, so that "1280x720" keeps
+    //vertical 720x1280 videos too.
+    let passes_resolution = match filter_cfg.min_resolution {
+        None => true,
+        Some((min_width, min_height)) => cache
+            .fetch_stats(src_path)
+            .map(|stats| {
+                let (width, height) = stats.resolution();
+                (width >= min_width && height >= min_height)
+                    || (width >= min_height && height >= min_width)
+            })
+            .unwrap_or(false),
+    };
+
+    passes_audio && passes_resolution
+}
+
+//Run f in the given thread pool, or in rayon's global pool if there is none.
+fn install<R: Send>(pool: Option<&rayon::ThreadPool>, f: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
 fn update_hash_cache(
+    cands: &FileProjection,
+    refs: &FileProjection,
+    nonfatal_errs: &mut Vec<AppError>,
+    cache: &VideoHashFilesystemCache,
+) -> Result<(), AppError> {
+    nonfatal_errs.extend(
+        cache
+            .update_using_fs(cands)?
+            .into_iter()
+            .map(AppError::from),
+    );
+    nonfatal_errs.extend(cache.update_using_fs(refs)?.into_iter().map(AppError::from));
+    cache.save()?;
+    Ok(())
+}
+
+//Hash the given files (and no others) into the cache, for modes which only look at a few files.
+fn hash_files(
+    src_paths: &[&PathBuf],
+    excl_exts: &[OsString],
+    nonfatal_errs: &mut Vec<AppError>,
+    cache: &VideoHashFilesystemCache,
+) -> Result<(), AppError> {
+    let mut projection = FileProjection::new(src_paths, &[] as &[PathBuf], excl_exts)
+        .map_err(AppError::from_cand_exclusion_error)?;
+    match projection.project_using_fs() {
+        Ok(projection_errs) => nonfatal_errs.extend(
+            projection_errs
+                .into_iter()
+                .map(|e| AppError::FileSearchError(e.path().unwrap().to_path_buf(), e)),
+        ),
+        Err(FileProjectionError::PathNotFound(path)) => {
+            return Err(AppError::CandPathNotFoundError(path))
+        }
+        Err(_) => unreachable!(),
+    }
+    nonfatal_errs.extend(
+        cache
+            .update_using_fs(&projection)?
+            .into_iter()
+            .map(AppError::from),
+    );
+    cache.save()?;
+    Ok(())
+}
+
+//Find all video files under --files and --with-refs on the filesystem.
+fn project_cands_and_refs_using_fs(
     cand_dirs: &[PathBuf],
     cand_excls: &[PathBuf],
     excl_exts: &[OsString],
     ref_dirs: &[PathBuf],
     ref_excls: &[PathBuf],
+    path_filter: &PathFilter,
     nonfatal_errs: &mut Vec<AppError>,
-    cache: &VideoHashFilesystemCache,
-) -> Result<(), AppError> {
+) -> Result<(FileProjection, FileProjection), AppError> {
     let mut cands = FileProjection::new(cand_dirs, cand_excls, excl_exts)
         .map_err(AppError::from_cand_exclusion_error)?;
     let mut refs = FileProjection::new(ref_dirs, ref_excls, excl_exts)
@@ -224,20 +840,30 @@ fn update_hash_cache(
             _ => unreachable!(),
         },
     };
-    nonfatal_errs.extend(
-        cache
-            .update_using_fs(&cands)?
-            .into_iter()
-            .map(AppError::from),
-    );
-    nonfatal_errs.extend(
-        cache
-            .update_using_fs(&refs)?
-            .into_iter()
-            .map(AppError::from),
-    );
-    cache.save()?;
-    Ok(())
+    path_filter.apply_to_cands(&mut cands);
+    path_filter.apply_to_refs(&mut refs);
+    Ok((cands, refs))
+}
+
+//Find the files under --files and --with-refs in a list of files, without looking at the filesystem.
+fn project_cands_and_refs_using_list(
+    cand_dirs: &[PathBuf],
+    cand_excls: &[PathBuf],
+    excl_exts: &[OsString],
+    ref_dirs: &[PathBuf],
+    ref_excls: &[PathBuf],
+    path_filter: &PathFilter,
+    src_paths: &HashSet<PathBuf>,
+) -> Result<(FileProjection, FileProjection), AppError> {
+    let mut cands = FileProjection::new(cand_dirs, cand_excls, excl_exts)
+        .map_err(AppError::from_cand_exclusion_error)?;
+    cands.project_using_list(src_paths);
+    path_filter.apply_to_cands(&mut cands);
+    let mut refs = FileProjection::new(ref_dirs, ref_excls, excl_exts)
+        .map_err(AppError::from_ref_exclusion_error)?;
+    refs.project_using_list(src_paths);
+    path_filter.apply_to_refs(&mut refs);
+    Ok((cands, refs))
 }
 
 //if any of the app's starting cand paths is inside the app's ref paths, then we'll add those paths to the ref paths' excl
@@ -294,33 +920,193 @@ fn resolve_shadowing_paths_of_cands_and_refs(
 
 pub fn obtain_thunks(
     cfg: &AppCfg,
-    cand_hashes: Vec<VideoHash>,
-    ref_hashes: Vec<VideoHash>,
-) -> Vec<MatchGroup> {
+    cache: &VideoHashFilesystemCache,
+    cand_paths: &[&Path],
+    ref_paths: &[&Path],
+) -> Vec<DupGroup> {
     //sanity check: Warn the user if no files were selected for the search
-    if cand_hashes.is_empty() {
+    if cand_paths.is_empty() {
         warn!("No files were found at the paths given by --files. No results will be returned.")
     }
 
     //sanity check: Warn the user if no refs were selected (but only if the user asked for refs)
-    if !cfg.dir_cfg.ref_dirs.is_empty() && ref_hashes.is_empty() {
+    if !cfg.dir_cfg.ref_dirs.is_empty() && ref_paths.is_empty() {
         warn!("No reference files were found at the paths given by --with-refs. No results will be returned.")
     }
 
+    let ratio = match cfg.prefilter_duration {
+        Some(ratio) => ratio,
+        None => {
+            return search_hashes(
+                cfg,
+                fetch_hashes(cache, cand_paths),
+                fetch_hashes(cache, ref_paths),
+            )
+        }
+    };
+
+    //Videos in different buckets are never compared, so each bucket can be searched on its own. In a
+    //with-refs search, buckets with no references can't contain any matches.
+    //
+    //Videos with no known duration could match a video of any duration, so they are added to every bucket. A
+    //group made up only of them would be found in every bucket, so those groups are only kept from a search of
+    //the unknown videos on their own.
+    let ref_search = !ref_paths.is_empty();
+    let (buckets, (unknown_cands, unknown_refs)) =
+        duration_buckets(cache, cand_paths, ref_paths, ratio);
+    let unknown_paths = unknown_cands
+        .iter()
+        .chain(unknown_refs.iter())
+        .copied()
+        .collect::<HashSet<_>>();
+
+    let mut groups = buckets
+        .into_iter()
+        .map(|(mut bucket_cands, mut bucket_refs)| {
+            bucket_cands.extend(&unknown_cands);
+            bucket_refs.extend(&unknown_refs);
+            (bucket_cands, bucket_refs)
+        })
+        .filter(|(_bucket_cands, bucket_refs)| !ref_search || !bucket_refs.is_empty())
+        .flat_map(|(bucket_cands, bucket_refs)| {
+            search_hashes(
+                cfg,
+                fetch_hashes(cache, bucket_cands),
+                fetch_hashes(cache, bucket_refs),
+            )
+        })
+        .filter(|group| {
+            search_output::group_paths(group).any(|src_path| !unknown_paths.contains(src_path))
+        })
+        .collect::<Vec<_>>();
+
+    if !unknown_cands.is_empty() && (!ref_search || !unknown_refs.is_empty()) {
+        groups.extend(search_hashes(
+            cfg,
+            fetch_hashes(cache, &unknown_cands),
+            fetch_hashes(cache, &unknown_refs),
+        ));
+    }
+
+    groups
+}
+
+//The candidates and references of a bucket of videos with similar durations.
+type DurationBucket<'a> = (Vec<&'a Path>, Vec<&'a Path>);
+
+//Split candidates and references into buckets of similar duration. Videos are sorted by duration and a new
+//bucket is started wherever the shorter of two neighbouring videos is more than `ratio` shorter than the
+//longer one. So any two videos in different buckets differ by at least that much. The candidates and references
+//with no stats are returned separately, after the buckets.
+fn duration_buckets<'a>(
+    cache: &VideoHashFilesystemCache,
+    cand_paths: &[&'a Path],
+    ref_paths: &[&'a Path],
+    ratio: f64,
+) -> (Vec<DurationBucket<'a>>, DurationBucket<'a>) {
+    let duration = |src_path: &Path| {
+        cache
+            .fetch_stats(src_path)
+            .ok()
+            .map(|stats| stats.duration())
+    };
+
+    let mut unknown = (vec![], vec![]);
+    let mut videos = vec![];
+    for (&src_path, is_ref) in cand_paths
+        .iter()
+        .map(|p| (p, false))
+        .chain(ref_paths.iter().map(|p| (p, true)))
+    {
+        match duration(src_path) {
+            Some(duration) => videos.push((duration, src_path, is_ref)),
+            None if is_ref => unknown.1.push(src_path),
+            None => unknown.0.push(src_path),
+        }
+    }
+    videos.sort_by(|(a, _, _), (b, _, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut buckets = vec![];
+    let mut bucket: DurationBucket = (vec![], vec![]);
+    let mut prev_duration = None;
+    for (duration, src_path, is_ref) in videos {
+        if let Some(prev_duration) = prev_duration {
+            if prev_duration < duration * (1.0 - ratio) {
+                buckets.push(std::mem::take(&mut bucket));
+            }
+        }
+        prev_duration = Some(duration);
+
+        if is_ref {
+            bucket.1.push(src_path);
+        } else {
+            bucket.0.push(src_path);
+        }
+    }
+    buckets.push(bucket);
+
+    debug!(
+        "Split videos into {} buckets of similar duration, with {} videos of unknown duration",
+        buckets.len(),
+        unknown.0.len() + unknown.1.len()
+    );
+    (buckets, unknown)
+}
+
+// the unwraps here are infallible, as the paths being fetched are sourced from the cache itself.
+fn fetch_hashes(
+    cache: &VideoHashFilesystemCache,
+    src_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+) -> Vec<VideoHash> {
+    src_paths
+        .into_iter()
+        .map(|src_path| cache.fetch(src_path.as_ref()).unwrap())
+        .collect()
+}
+
+fn search_hashes(
+    cfg: &AppCfg,
+    cand_hashes: Vec<VideoHash>,
+    ref_hashes: Vec<VideoHash>,
+) -> Vec<DupGroup> {
     //If there are just cands, then perform a find-all search. Otherwise perform a with-refs search.
-    let match_set = if ref_hashes.is_empty() {
+    let match_set: Vec<MatchGroup> = if ref_hashes.is_empty() {
         search(cand_hashes, cfg.tolerance)
+    } else if cfg.multi_ref {
+        //A reference search removes each candidate from the search once it has matched a reference. So to
+        //report a candidate against every reference it matches, search against each reference separately.
+        //The library can't reuse one search over the candidates between references, so this takes
+        //O(refs * cands) time. Each search holds its own copy of the candidates, so the references are
+        //searched a chunk at a time, one per thread, to keep at most one copy per thread in memory.
+        use rayon::prelude::*;
+        let chunk_size = rayon::current_num_threads().max(1);
+        ref_hashes
+            .chunks(chunk_size)
+            .flat_map(|ref_chunk| {
+                ref_chunk
+                    .par_iter()
+                    .flat_map(|ref_hash| {
+                        search_with_references(
+                            std::iter::once(ref_hash.clone()),
+                            cand_hashes.iter().cloned(),
+                            cfg.tolerance,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
     } else {
         search_with_references(ref_hashes, cand_hashes, cfg.tolerance)
     };
 
-    match_set
+    match_set.into_iter().map(DupGroup::from).collect()
 }
 
 fn print_fatal_err(fatal_err: &AppError, verbosity: ReportVerbosity) {
-    error!(target: "app-errorlog", "{}", fatal_err);
-
-    if verbosity == ReportVerbosity::Verbose {
+    if verbosity != ReportVerbosity::Verbose {
+        error!(target: "app-errorlog", "{}", fatal_err.display_chain());
+    } else {
+        error!(target: "app-errorlog", "{}", fatal_err);
         let mut source: Option<&(dyn Error + 'static)> = fatal_err.source();
         while let Some(e) = source {
             error!(target: "app-errorlog", "    caused by: {}", e);
@@ -334,7 +1120,7 @@ fn print_nonfatal_errs(nonfatal_errs: Vec<AppError>) {
         .iter()
         .filter(|err| !matches!(err, AppError::CacheErrror(_)))
     {
-        warn!("{}", err);
+        warn!("{}", err.display_chain());
     }
 }
 
@@ -360,58 +1146,185 @@ pub fn configure_logs(verbosity: ReportVerbosity) {
     .expect("TermLogger failed to initialize");
 }
 
-fn print_search_results(search_output: &SearchOutput, unique_paths: &[&Path], app_cfg: &AppCfg) {
-    let output_cfg = &app_cfg.output_cfg;
+fn print_search_results(
+    report: &SearchReport,
+    cache: &VideoHashFilesystemCache,
+    output_cfg: &OutputCfg,
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
     if output_cfg.print_unique {
-        if output_cfg.json_output {
-            let stdout = BufWriter::new(std::io::stdout());
-            serde_json::to_writer_pretty(stdout, &json!(unique_paths)).unwrap_or_default();
-            println!();
+        if output_cfg.json_rich {
+            let output_vec = report
+                .unique
+                .iter()
+                .map(|unique_path| member_json(unique_path, cache, output_cfg))
+                .collect::<Vec<_>>();
+
+            serde_json::to_writer_pretty(&mut *out, &json!(output_vec))?;
+            writeln!(out)?;
+        } else if output_cfg.json_lines {
+            for unique_path in &report.unique {
+                serde_json::to_writer(&mut *out, &output_cfg.output_path(unique_path))?;
+                writeln!(out)?;
+                out.flush()?;
+            }
+        } else if output_cfg.json_output {
+            let output_vec = report
+                .unique
+                .iter()
+                .map(|unique_path| output_cfg.output_path(unique_path))
+                .collect::<Vec<_>>();
+
+            serde_json::to_writer_pretty(&mut *out, &json!(output_vec))?;
+            writeln!(out)?;
         } else {
-            unique_paths.iter().for_each(|unique_file| {
-                println!("{}", unique_file.display());
-            });
+            for unique_file in &report.unique {
+                writeln!(out, "{}", output_cfg.output_path(unique_file).display())?;
+            }
         }
     }
 
     if output_cfg.print_duplicates {
-        if output_cfg.json_output {
+        if output_cfg.json_rich {
+            let output_vec = report
+                .groups
+                .iter()
+                .map(|group| {
+                    let mut group_json = json!({
+                        "distance": group.distance,
+                        "similarity": group.similarity(),
+                        "reference": group
+                            .reference
+                            .map(|reference| member_json(reference, cache, output_cfg)),
+                        "duplicates": group
+                            .duplicates
+                            .iter()
+                            .map(|dup_path| member_json(dup_path, cache, output_cfg))
+                            .collect::<Vec<_>>(),
+                    });
+                    if let Some(kind) = group.kind {
+                        group_json["kind"] = json!(kind);
+                    }
+                    if let Some(ref_set) = group.ref_set {
+                        group_json["ref_set"] = json!(ref_set);
+                    }
+                    group_json
+                })
+                .collect::<Vec<_>>();
+
+            serde_json::to_writer_pretty(&mut *out, &json!(output_vec))?;
+            writeln!(out)?;
+        } else if output_cfg.json_output || output_cfg.json_lines {
             #[derive(Serialize)]
             struct JsonStruct<'a> {
                 reference: Option<&'a Path>,
                 duplicates: Vec<&'a Path>,
+                similarity: f64,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                kind: Option<GroupKind>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                ref_set: Option<&'a str>,
             }
 
-            let output_vec: Vec<JsonStruct> = search_output
-                .dup_groups()
-                .map(|group| JsonStruct {
-                    reference: group.reference(),
-                    duplicates: group.duplicates().collect(),
-                })
-                .collect();
+            let group_jsons = report.groups.iter().map(|group| JsonStruct {
+                reference: group
+                    .reference
+                    .map(|reference| output_cfg.output_path(reference)),
+                duplicates: group
+                    .duplicates
+                    .iter()
+                    .map(|dup_path| output_cfg.output_path(dup_path))
+                    .collect(),
+                similarity: group.similarity(),
+                kind: group.kind,
+                ref_set: group.ref_set,
+            });
 
-            let stdout = BufWriter::new(std::io::stdout());
-            serde_json::to_writer_pretty(stdout, &json!(output_vec)).unwrap_or_default();
-            println!();
+            //With --json-lines each group is flushed as soon as it is written, so that consumers reading from a
+            //pipe can start on the first groups before the rest are printed.
+            if output_cfg.json_lines {
+                for group_json in group_jsons {
+                    serde_json::to_writer(&mut *out, &group_json)?;
+                    writeln!(out)?;
+                    out.flush()?;
+                }
+            } else {
+                let output_vec = group_jsons.collect::<Vec<_>>();
+                serde_json::to_writer_pretty(&mut *out, &json!(output_vec))?;
+                writeln!(out)?;
+            }
         } else {
-            for group in search_output.dup_groups() {
-                if let Some(video) = group.reference() {
-                    println!("{}", video.display());
+            for group in &report.groups {
+                if let Some(kind) = group.kind {
+                    writeln!(out, "[{}]", kind.label())?;
+                }
+                if let Some(ref_set) = group.ref_set {
+                    writeln!(out, "[ref-set: {}]", ref_set)?;
                 }
-                for video in group.duplicates() {
-                    println!("{}", video.display());
+                writeln!(out, "[similarity: {:.3}]", group.similarity())?;
+                if let Some(video) = group.reference {
+                    writeln!(out, "{}", output_cfg.output_path(video).display())?;
                 }
-                println!();
+                for video in &group.duplicates {
+                    writeln!(out, "{}", output_cfg.output_path(video).display())?;
+                }
+                writeln!(out)?;
             }
         }
     }
+
+    out.flush()
+}
+
+pub fn stats_json(stats: &VideoStats) -> serde_json::Value {
+    let (width, height) = stats.resolution();
+    json!({
+        "duration": stats.duration(),
+        "resolution": [width, height],
+        "size": stats.size(),
+        "bit_rate": stats.bit_rate(),
+        "has_audio": stats.has_audio(),
+        "png_size": stats.png_size(),
+    })
+}
+
+//A path annotated with its stats, for --json-rich. If the stats could not be fetched from the cache then
+//only the path is given.
+fn member_json(
+    path: &Path,
+    cache: &VideoHashFilesystemCache,
+    output_cfg: &OutputCfg,
+) -> serde_json::Value {
+    let mut member = match cache.fetch_stats(path) {
+        Ok(stats) => stats_json(&stats),
+        Err(_) => json!({}),
+    };
+    member["path"] = json!(output_cfg.output_path(path));
+    member
+}
+
+//The number of new hashes after which the cache is saved during an update, unless changed with
+//--cache-flush-interval
+const DEFAULT_CACHE_FLUSH_INTERVAL: u32 = 100;
+
+//The width and height of each frame in a match image, unless changed with --thumb-size
+pub const DEFAULT_THUMB_SIZE: u32 = 200;
+
+//The number of frames of each video in a match image, and the rate they are sampled at, unless changed with
+//--thumb-frames and --thumb-fps
+pub const DEFAULT_THUMB_FRAMES: u32 = 7;
+pub const DEFAULT_THUMB_FPS: &str = "1/5";
+
+pub fn match_image_font() -> rusttype::Font<'static> {
+    rusttype::Font::try_from_bytes(include_bytes!("font/NotoSans-Regular.ttf")).unwrap()
 }
 
-fn write_image(
+pub fn write_image(
     reference: Option<&Path>,
     duplicates: impl IntoIterator<Item = impl AsRef<Path>>,
     output_path: &Path,
     font: &rusttype::Font,
+    layout: &MatchImageLayout,
 ) {
     //use imageproc::*;
     use image::GenericImage;
@@ -423,8 +1336,19 @@ fn write_image(
             "Writing match image to {}", output_path.display()
     );
 
-    pub fn grid_images(images: &[(String, Vec<RgbImage>)], font: &rusttype::Font) -> RgbImage {
-        let (img_x, img_y) = images.get(0).unwrap().1.get(0).unwrap().dimensions();
+    pub fn grid_images(
+        images: &[(String, Vec<RgbImage>)],
+        font: &rusttype::Font,
+        thumb_size: u32,
+    ) -> RgbImage {
+        //Every frame is resized to the same size, but a video may have no frames at all.
+        let (img_x, img_y) = images
+            .iter()
+            .find_map(|(_src_path, imgs)| imgs.first())
+            .map_or((thumb_size, thumb_size), |img| img.dimensions());
+
+        //There are as many columns as the video with the most frames, which may be fewer than were asked for
+        //if every video is short.
         let grid_num_x = images
             .iter()
             .map(|(_src_path, imgs)| imgs.len())
@@ -478,8 +1402,8 @@ fn write_image(
             (
                 src_path.to_string_lossy().to_string(),
                 ffmpeg_cmdline_utils::FfmpegFrameReaderBuilder::new(src_path.to_path_buf())
-                    .num_frames(7)
-                    .fps("1/5")
+                    .num_frames(layout.num_frames)
+                    .fps(&layout.fps)
                     .spawn()
                     .ok()
                     .map(|(frames_iter, _stats)| {
@@ -487,8 +1411,8 @@ fn write_image(
                             .map(|img| {
                                 image::imageops::resize(
                                     &img,
-                                    200,
-                                    200,
+                                    layout.thumb_size,
+                                    layout.thumb_size,
                                     image::imageops::FilterType::Triangle,
                                 )
                             })
@@ -499,7 +1423,7 @@ fn write_image(
         })
         .collect::<Vec<_>>();
 
-    let output_buf = grid_images(&all_thumbs, font);
+    let output_buf = grid_images(&all_thumbs, font, layout.thumb_size);
     std::fs::create_dir_all(output_path.parent().unwrap()).unwrap();
     output_buf.save(output_path).unwrap();
 }
@@ -1,5 +1,7 @@
 use std::{
-    ffi::OsStr,
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    io::Read,
     path::{Path, PathBuf},
 };
 
@@ -8,30 +10,101 @@ use vid_dup_finder_lib::*;
 use crate::app::*;
 use AppError::*;
 
+//The extensions excluded from searches unless --exclude-exts or --include-exts is given. These match the
+//extensions that the library skips when enumerating files.
+const DEFAULT_EXCL_EXTS: &[&str] = &["png", "jpg", "bmp", "jpeg", "gif", "txt", "text", "db"];
+
 // file specification
 const FILE_PATHS: &str = "Directories/files to search";
+const FILES_FROM: &str = "Read files to search from a file";
 const REF_PATHS: &str = "Reference file paths";
+const REF_SETS: &str = "Named reference file paths";
 const EXCL_FILE_PATHS: &str = "Exclude file paths";
+const EXCL_REGEX: &str = "Exclude file paths matching a regex";
+const MIN_SIZE: &str = "Exclude files smaller than this size";
+const MAX_SIZE: &str = "Exclude files larger than this size";
+const PROTECT_PATHS: &str = "Protected file paths";
+const KEEP_LIST: &str = "Keep list";
+const NO_RECURSE: &str = "Do not recurse into subdirectories";
+const FOLLOW_SYMLINKS: &str = "Follow symlinks";
 const EXCL_EXTS: &str = "Exclude file extensions";
+const INCL_EXTS: &str = "Include file extensions";
 
 //cache update settings
 const CACHE_FILE: &str = "Cache file path";
 const UPDATE_CACHE_ONLY: &str = "Update cache only. Do not perform any search";
+const PRUNE_CACHE: &str = "Remove missing files from the cache";
+const COMPACT_CACHE: &str = "Rewrite the cache without stale entries";
+const DISCARD_NON_VIDEOS: &str = "Discard cache entries for files that could not be hashed";
+const WAIT_FOR_LOCK: &str = "Wait for other instances to finish with the cache";
 const NO_UPDATE_CACHE: &str = "Do not update the cache. Search using alreaady-cached data";
+const CACHE_PLAN: &str = "Show what a cache update would do";
+const RESUME: &str = "Resume an interrupted cache update";
+const CACHE_FLUSH_INTERVAL: &str = "Save the cache after this many files are hashed";
+const INSPECT: &str = "Print the cache entry of a file";
+const DUMP_CACHE_JSON: &str = "Write the whole cache as JSON";
+const EXPLAIN_MATCH: &str = "Explain the distance between two files";
+const COMPARE: &str = "Compare two files";
+const TUNE_TOLERANCE: &str = "Tune the tolerance against labeled files";
+const FFMPEG_NICE: &str = "Ffmpeg niceness";
+const THREADS: &str = "Number of threads";
+const INVALIDATE_ON_FFMPEG_CHANGE: &str = "Rebuild the cache if ffmpeg has changed";
 
 //output settings
 const JSON_OUTPUT: &str = "Json output";
+const JSON_LINES: &str = "Newline delimited json output";
+const OUTPUT_FILE: &str = "Write results to a file";
+const JSON_RICH: &str = "Json output with stats";
+const PRESERVE_INPUT_ORDER: &str = "Preserve input order";
+const SORT_GROUPS_BY: &str = "Sort groups of duplicates";
+const LADDER_ORDER: &str = "Order duplicates by resolution";
+const MAX_GROUPS: &str = "Report at most N groups";
+const DIR_SUMMARY: &str = "Directory summary";
+const STATS: &str = "Print a summary of the run";
+const RELATIVE_TO: &str = "Print paths relative to a directory";
+const EXPORT_UNIQUE_TO: &str = "Export a deduplicated copy of all files";
+const EXPORT_FLATTEN: &str = "Flatten exported files";
+const EXPORT_HARDLINK: &str = "Hardlink exported files";
+const FUZZY_RENAME: &str = "Label renamed copies";
 const OUTPUT_THUMBS_DIR: &str = "Output thumbnails to the given directory";
+const THUMBS_CONCURRENCY: &str = "Number of thumbnails to write at once";
+const THUMB_SIZE: &str = "Thumbnail size";
+const THUMB_FRAMES: &str = "Thumbnail frames";
+const THUMB_FPS: &str = "Thumbnail frame rate";
 
 //gui settings
 const GUI: &str = "Run gui for deconsting duplicates";
+const TUI: &str = "Resolve duplicates in the terminal";
+const AUTO_RESOLVE: &str = "Resolve duplicates automatically";
+const MOVE_DUPS_TO: &str = "Move duplicates to a directory";
 const GUI_TRASH_PATH: &str = "Gui trash path";
+const GUI_ARCHIVE_PATH: &str = "Gui archive path";
+const PLAYER: &str = "Video player";
+const FILE_MANAGER: &str = "File manager";
+const RESULTS_FILE: &str = "Resolve saved search results";
+const BASELINE: &str = "Only report new duplicates";
 
 //search configuration
 const TOLERANCE: &str = "Comparison tolerance";
+const AUTO_TOLERANCE: &str = "Suggest a comparison tolerance";
+const ROBUSTNESS_CHECK: &str = "Check tolerance against transformed copies of a file";
+const PREFER_SUBTITLES: &str = "Prefer keeping videos with subtitles";
+const REQUIRE_KEEPER_READABLE: &str = "Check the kept video can be read";
+const MULTI_REF: &str = "Match candidates against every reference";
+const PREFILTER_DURATION: &str = "Only compare videos of similar duration";
+const MIN_GROUP_SIZE: &str = "Minimum group size";
+const TRANSITIVE: &str = "Merge overlapping groups";
+const VERIFY_IDENTICAL: &str = "Verify identical videos byte by byte";
+const EXACT_FIRST: &str = "Group identical files before searching";
+const AFFIRM_AUDIO: &str = "Check that matching videos have the same audio";
+const REQUIRE_AUDIO: &str = "Require audio";
+const EXCLUDE_AUDIO: &str = "Exclude audio";
+const MIN_RESOLUTION: &str = "Minimum resolution";
 const PRINT_UNIQUE: &str = "Print unique items (default is to print duplicate items)";
+const SORT_UNIQUE_BY: &str = "Sort unique items";
 
 const ARGS_FILE: &str = "Args file";
+const DRY_RUN: &str = "Dry run";
 
 const VERBOSITY_QUIET: &str = "Quiet";
 const VERBOSITY_VERBOSE: &str = "Verbose";
@@ -44,30 +117,97 @@ fn build_app() -> clap::App<'static, 'static> {
         //
         // file specification
         FILE_PATHS,
+        FILES_FROM,
         REF_PATHS,
+        REF_SETS,
         EXCL_FILE_PATHS,
+        EXCL_REGEX,
+        MIN_SIZE,
+        MAX_SIZE,
+        PROTECT_PATHS,
+        KEEP_LIST,
         EXCL_EXTS,
+        INCL_EXTS,
+        NO_RECURSE,
+        FOLLOW_SYMLINKS,
         //
         //search modifiers
         TOLERANCE,
+        AUTO_TOLERANCE,
+        ROBUSTNESS_CHECK,
+        MULTI_REF,
+        PREFILTER_DURATION,
+        MIN_GROUP_SIZE,
+        TRANSITIVE,
+        VERIFY_IDENTICAL,
+        EXACT_FIRST,
+        AFFIRM_AUDIO,
+        PREFER_SUBTITLES,
+        REQUIRE_KEEPER_READABLE,
+        REQUIRE_AUDIO,
+        EXCLUDE_AUDIO,
+        MIN_RESOLUTION,
         //
         //caching
         CACHE_FILE,
         UPDATE_CACHE_ONLY,
+        PRUNE_CACHE,
+        COMPACT_CACHE,
+        DISCARD_NON_VIDEOS,
+        WAIT_FOR_LOCK,
+        CACHE_PLAN,
+        INSPECT,
+        DUMP_CACHE_JSON,
+        EXPLAIN_MATCH,
+        COMPARE,
+        TUNE_TOLERANCE,
         NO_UPDATE_CACHE,
+        RESUME,
+        CACHE_FLUSH_INTERVAL,
+        INVALIDATE_ON_FFMPEG_CHANGE,
+        FFMPEG_NICE,
+        THREADS,
         //
         //outputs
         PRINT_UNIQUE,
+        SORT_UNIQUE_BY,
         JSON_OUTPUT,
+        JSON_LINES,
+        OUTPUT_FILE,
+        JSON_RICH,
+        PRESERVE_INPUT_ORDER,
+        SORT_GROUPS_BY,
+        LADDER_ORDER,
+        MAX_GROUPS,
+        FUZZY_RENAME,
+        DIR_SUMMARY,
+        STATS,
+        RELATIVE_TO,
+        EXPORT_UNIQUE_TO,
+        EXPORT_FLATTEN,
+        EXPORT_HARDLINK,
         OUTPUT_THUMBS_DIR,
+        THUMBS_CONCURRENCY,
+        THUMB_SIZE,
+        THUMB_FRAMES,
+        THUMB_FPS,
         VERBOSITY_QUIET,
         VERBOSITY_VERBOSE,
         //
         //gui
         GUI,
+        TUI,
+        AUTO_RESOLVE,
+        MOVE_DUPS_TO,
         GUI_TRASH_PATH,
+        GUI_ARCHIVE_PATH,
+        PLAYER,
+        FILE_MANAGER,
+        RESULTS_FILE,
+        BASELINE,
         //argument replacement
         ARGS_FILE,
+        DRY_RUN,
     ];
 
     let get_ordering = |arg_name: &str| -> usize {
@@ -89,15 +229,18 @@ fn build_app() -> clap::App<'static, 'static> {
     let tol = NormalizedTolerance::default();
     let default_tol_string: &'static str = Box::leak(format!("{}", tol.value()).into_boxed_str());
 
+    //There may be no default cache location (e.g. if $HOME is not set), in which case --cache-file must be given.
     let default_cache_file =
         directories_next::ProjectDirs::from("", "vid_dup_finder", "vid_dup_finder")
-            .unwrap()
-            .cache_dir()
-            .join("vid_dup_finder_cache.bin")
-            .to_str()
-            .unwrap()
-            .to_owned();
-    let default_cache_file: &'static str = Box::leak(default_cache_file.into_boxed_str());
+            .and_then(|dirs| {
+                dirs.cache_dir()
+                    .join("vid_dup_finder_cache.bin")
+                    .to_str()
+                    .map(str::to_owned)
+            })
+            .map(|default_cache_file| -> &'static str {
+                Box::leak(default_cache_file.into_boxed_str())
+            });
 
     //args are not added through method chaining because rustfmt struggles with very long expressions.
     let mut clap_app = clap::App::new("Video duplicate finder")
@@ -109,7 +252,7 @@ fn build_app() -> clap::App<'static, 'static> {
     clap_app = clap_app.arg(
         clap::Arg::with_name(FILE_PATHS)
             .long("files")
-            .required_unless(ARGS_FILE)
+            .required_unless_one(&[ARGS_FILE, FILES_FROM, ROBUSTNESS_CHECK, PRUNE_CACHE, COMPACT_CACHE, INSPECT, DUMP_CACHE_JSON, EXPLAIN_MATCH, COMPARE, TUNE_TOLERANCE, RESULTS_FILE])
             .multiple(true)
             .min_values(1)
             .takes_value(true)
@@ -117,6 +260,14 @@ fn build_app() -> clap::App<'static, 'static> {
             .display_order(get_ordering(FILE_PATHS)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(FILES_FROM)
+            .long("files-from")
+            .takes_value(true)
+            .help("Read paths to search from the given file, one per line, as if they were given to --files. Use '-' to read from stdin (not with --tui, which needs stdin for keypresses), e.g. 'find . -name \"*.mp4\" | vid_dup_finder --files-from -'. Relative paths are relative to the current directory, and spaces at the start or end of a line are part of the path. Paths which do not exist are reported as errors but do not stop the search")
+            .display_order(get_ordering(FILES_FROM)),
+    );
+
     clap_app = clap_app.arg(
         clap::Arg::with_name(REF_PATHS)
             .long("with-refs")
@@ -127,6 +278,17 @@ fn build_app() -> clap::App<'static, 'static> {
             .display_order(get_ordering(REF_PATHS)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(REF_SETS)
+            .long("ref-set")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true)
+            .help("A named set of reference video files, given as NAME:PATH. May be given multiple times, and several paths may share a name. Files given by --files are searched for duplicates against each set separately, and each match is labeled with the name of its set")
+            .conflicts_with(REF_PATHS)
+            .display_order(get_ordering(REF_SETS)),
+    );
+
     clap_app = clap_app.arg(
         clap::Arg::with_name(EXCL_FILE_PATHS)
             .long("exclude")
@@ -137,26 +299,98 @@ fn build_app() -> clap::App<'static, 'static> {
             .display_order(get_ordering(EXCL_FILE_PATHS)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(EXCL_REGEX)
+            .long("exclude-regex")
+            .multiple(true)
+            .takes_value(true)
+            .number_of_values(1)
+            .help("Exclude files whose full path matches the given regular expression (e.g. \"sample|trailer|/proxies/\"). May be given more than once, in which case files matching any of the expressions are excluded. Excluded files are never hashed")
+            .display_order(get_ordering(EXCL_REGEX)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(MIN_SIZE)
+            .long("min-size")
+            .takes_value(true)
+            .help("Exclude files smaller than the given size, e.g. \"100MB\" or \"1.5GiB\". Excluded files are never hashed")
+            .display_order(get_ordering(MIN_SIZE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(MAX_SIZE)
+            .long("max-size")
+            .takes_value(true)
+            .help("Exclude files larger than the given size, e.g. \"4GB\". Excluded files are never hashed")
+            .display_order(get_ordering(MAX_SIZE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(PROTECT_PATHS)
+            .long("protect")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true)
+            .help("Directories whose files must never be deleted. May be given multiple times. When choosing which file in a group to keep, files in these directories are always chosen first, and are never trashed. Unlike --with-refs, files in these directories are still searched for duplicates of each other")
+            .display_order(get_ordering(PROTECT_PATHS)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(KEEP_LIST)
+            .long("keep-list")
+            .takes_value(true)
+            .help("A file containing paths of videos which must never be trashed, one per line. Listed videos are always kept when resolving a group, even when another video is chosen as the one to keep")
+            .display_order(get_ordering(KEEP_LIST)),
+    );
+
     clap_app = clap_app.arg(
         clap::Arg::with_name(EXCL_EXTS)
             .long("exclude-exts")
             .multiple(true)
             .min_values(1)
             .takes_value(true)
-            .help("File extensions to be excluded from searches. When specified the default file exclusion extensions will be replaced with the given values. Extensions must be comma separated with no spaces, e.g '--exclude-exts ext1,ext2,ext3'")
+            .help("File extensions to be excluded from searches. When specified the default file exclusion extensions (png,jpg,bmp,jpeg,gif,txt,text,db) will be replaced with the given values. Extensions must be comma separated with no spaces, e.g '--exclude-exts ext1,ext2,ext3'")
             .require_delimiter(true)
-            .default_value("png,jpg,bmp,jpeg,txt,text,db")
             .display_order(get_ordering(EXCL_EXTS)),
     );
 
     clap_app = clap_app.arg(
-        clap::Arg::with_name(CACHE_FILE)
-            .long("cache-file")
-            .default_value(default_cache_file)
-            .help("An optional custom location for the cache file (used to speed up repeated runs)")
-            .display_order(get_ordering(CACHE_FILE)),
+        clap::Arg::with_name(INCL_EXTS)
+            .long("include-exts")
+            .multiple(true)
+            .min_values(1)
+            .takes_value(true)
+            .help("Only search files with these extensions (ignoring case). Other files are skipped without being opened by ffmpeg. Extensions must be comma separated with no spaces, e.g '--include-exts mp4,mkv,avi'")
+            .require_delimiter(true)
+            .conflicts_with(EXCL_EXTS)
+            .display_order(get_ordering(INCL_EXTS)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(NO_RECURSE)
+            .long("no-recurse")
+            .takes_value(false)
+            .help("Only search files directly inside the directories given by --files, ignoring their subdirectories. Does not affect --with-refs")
+            .display_order(get_ordering(NO_RECURSE)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(FOLLOW_SYMLINKS)
+            .long("follow-symlinks")
+            .takes_value(false)
+            .help("Search files which are symlinks to videos. By default they are skipped. Symlinks to directories are never followed, so a symlink cycle cannot stop the search from finishing. If a symlink and the file it points to are both found, only one of them is searched")
+            .display_order(get_ordering(FOLLOW_SYMLINKS)),
+    );
+
+    let mut cache_file_arg = clap::Arg::with_name(CACHE_FILE)
+        .long("cache-file")
+        .help("An optional custom location for the cache file (used to speed up repeated runs)")
+        .display_order(get_ordering(CACHE_FILE));
+    if let Some(default_cache_file) = default_cache_file {
+        cache_file_arg = cache_file_arg.default_value(default_cache_file);
+    }
+    clap_app = clap_app.arg(cache_file_arg);
+
     clap_app = clap_app.arg(
         clap::Arg::with_name(UPDATE_CACHE_ONLY)
             .long("update-cache-only")
@@ -166,23 +400,187 @@ fn build_app() -> clap::App<'static, 'static> {
             .display_order(get_ordering(UPDATE_CACHE_ONLY)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(PRUNE_CACHE)
+            .long("prune-cache")
+            .help("Do not run a search. Remove every file which no longer exists from the cache, even if it is not inside --files or --with-refs, and then exit. With --dry-run the files are listed instead")
+            .conflicts_with_all(&[FILE_PATHS, REF_PATHS, REF_SETS, GUI, NO_UPDATE_CACHE, UPDATE_CACHE_ONLY])
+            .display_order(get_ordering(PRUNE_CACHE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(COMPACT_CACHE)
+            .long("compact-cache")
+            .help("Do not run a search. Remove every file which no longer exists from the cache (as --prune-cache does), rewrite the cache file, print its size before and after, and then exit. With --dry-run the entries that would be removed are listed instead")
+            .conflicts_with_all(&[FILE_PATHS, REF_PATHS, REF_SETS, GUI, NO_UPDATE_CACHE, UPDATE_CACHE_ONLY, PRUNE_CACHE])
+            .display_order(get_ordering(COMPACT_CACHE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(DISCARD_NON_VIDEOS)
+            .long("discard-non-videos")
+            .requires(COMPACT_CACHE)
+            .help("For use with --compact-cache: Also remove every file that could not be hashed (because it is not a video, or ffmpeg failed to read it), so that it is tried again at the next cache update")
+            .display_order(get_ordering(DISCARD_NON_VIDEOS)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(WAIT_FOR_LOCK)
+            .long("wait-for-lock")
+            .help("Only one instance at a time may update a cache file. Normally an instance exits with an error if another instance is using the cache. With this flag it waits for the other instance to finish instead")
+            .conflicts_with(NO_UPDATE_CACHE)
+            .display_order(get_ordering(WAIT_FOR_LOCK)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(CACHE_PLAN)
+            .long("cache-plan")
+            .help("Do not run a search or update the cache. Instead print how many files an update would hash for the first time, how many might need rehashing because they were modified after the cache was last saved, how many are unchanged, and how many would be removed from the cache because they no longer exist.")
+            .conflicts_with(GUI)
+            .conflicts_with(NO_UPDATE_CACHE)
+            .conflicts_with(UPDATE_CACHE_ONLY)
+            .conflicts_with(INVALIDATE_ON_FFMPEG_CHANGE)
+            .display_order(get_ordering(CACHE_PLAN)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(INSPECT)
+            .long("inspect")
+            .takes_value(true)
+            .help("Do not run a search or update the cache. Instead print everything the cache holds for the given file (its hash, its stats, or the error that occurred when they were created) as JSON")
+            .conflicts_with_all(&[FILE_PATHS, REF_PATHS, REF_SETS, GUI, UPDATE_CACHE_ONLY, CACHE_PLAN])
+            .display_order(get_ordering(INSPECT)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(DUMP_CACHE_JSON)
+            .long("dump-cache-json")
+            .takes_value(true)
+            .help("Do not run a search or update the cache. Instead write every entry in the cache to the given file as a JSON array, giving the path, status (\"Video\", or the error that occurred when the file was hashed) and stats of each file")
+            .conflicts_with_all(&[FILE_PATHS, REF_PATHS, REF_SETS, GUI, UPDATE_CACHE_ONLY, CACHE_PLAN, INSPECT])
+            .display_order(get_ordering(DUMP_CACHE_JSON)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(EXPLAIN_MATCH)
+            .long("explain-match")
+            .takes_value(true)
+            .number_of_values(2)
+            .value_names(&["A", "B"])
+            .help("Do not run a search or update the cache. Instead print, for each frame sampled when hashing, how much the spatial and temporal parts of the cached hashes of the two given files differ, along with the approximate timestamp of the frame")
+            .conflicts_with_all(&[FILE_PATHS, REF_PATHS, REF_SETS, GUI, UPDATE_CACHE_ONLY, CACHE_PLAN, INSPECT])
+            .display_order(get_ordering(EXPLAIN_MATCH)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(COMPARE)
+            .long("compare")
+            .takes_value(true)
+            .number_of_values(2)
+            .value_names(&["A", "B"])
+            .help("Do not run a search. Instead hash the two given files (unless they are already cached) and print the distance between them, along with estimates of how much their spatial and temporal hashes differ")
+            .conflicts_with_all(&[FILE_PATHS, REF_PATHS, REF_SETS, GUI, UPDATE_CACHE_ONLY, CACHE_PLAN, INSPECT, EXPLAIN_MATCH])
+            .display_order(get_ordering(COMPARE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(TUNE_TOLERANCE)
+            .long("tune-tolerance")
+            .takes_value(true)
+            .value_name("LABELS.csv")
+            .help("Do not run a search. Instead hash the files in the given labels file (unless they are already cached), print the precision, recall and F1 score of matching them at a range of tolerances, and suggest the tolerance with the best F1 score. Each line of the labels file holds two paths and a label of \"dup\" or \"not\", separated by commas (e.g. \"a.mp4,b.mp4,dup\"). Relative paths are relative to the directory of the labels file")
+            .conflicts_with_all(&[FILE_PATHS, REF_PATHS, REF_SETS, GUI, UPDATE_CACHE_ONLY, CACHE_PLAN, INSPECT, EXPLAIN_MATCH, COMPARE])
+            .display_order(get_ordering(TUNE_TOLERANCE)),
+    );
+
     #[cfg(all(target_family = "unix", feature = "gui"))]
-    let clap_app = clap_app.arg(
+    let mut clap_app = clap_app.arg(
         clap::Arg::with_name(GUI)
             .long("gui")
             .help("Start a GUI that aids in deleting duplicate videos.")
             .display_order(get_ordering(GUI)),
     );
 
-    #[cfg(all(target_family = "unix", feature = "gui"))]
-    let mut clap_app = clap_app.arg(
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(TUI)
+            .long("tui")
+            .help("Resolve groups of duplicates one at a time in the terminal, without a GUI. For each group the stats of each video are printed, and a resolution is read from stdin using the same syntax as the GUI's keep command (e.g. \"0\", \"1 as 0\", \"1 at 0\")")
+            .conflicts_with(GUI)
+            .conflicts_with(UPDATE_CACHE_ONLY)
+            .conflicts_with(PRINT_UNIQUE)
+            .display_order(get_ordering(TUI)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(AUTO_RESOLVE)
+            .long("auto-resolve")
+            .takes_value(true)
+            .possible_values(&["largest-res", "largest-file", "largest-pngsize", "highest-bitrate"])
+            .help("Resolve every group of duplicates without a GUI, by keeping the video with the largest resolution, largest file, largest png size (a measure of image detail), or highest bitrate, and trashing the rest in the same way as the GUI's keep command. If a group contains files from --protect or --keep-list, only those are considered for keeping. Groups where more than one video is best are skipped. Use --dry-run to see what would be done")
+            .conflicts_with_all(&[GUI, TUI, UPDATE_CACHE_ONLY, PRINT_UNIQUE])
+            .display_order(get_ordering(AUTO_RESOLVE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(MOVE_DUPS_TO)
+            .long("move-dups-to")
+            .takes_value(true)
+            .requires(AUTO_RESOLVE)
+            .help("For use with --auto-resolve: Move duplicates into the given directory for review instead of trashing them. Each file keeps its path relative to the directory it was found in (given with --files or --with-refs). If a file is already there then a suffix such as \" (1)\" is added to the new name")
+            .display_order(get_ordering(MOVE_DUPS_TO)),
+    );
+
+    clap_app = clap_app.arg(
         clap::Arg::with_name(GUI_TRASH_PATH)
             .long("gui-trash-path")
-            .hidden(true)
+            .takes_value(true)
+            .help("For use in the gui or tui: Directory that duplicate files will be moved to when using the \"keep\" operation, instead of the system trash (or recycle bin). The directory must belong to the current user")
+            .display_order(get_ordering(GUI_TRASH_PATH)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(GUI_ARCHIVE_PATH)
+            .long("archive-resolved")
+            .takes_value(true)
             .help(
-                "For use in the gui: Directory that duplicate files will be moved to when using the \"keep\" operation",
+                "For use in the gui or tui: When a group of duplicates is resolved, write a thumbnail image of the group to the given directory before any files are trashed",
             )
-            .display_order(get_ordering(GUI_TRASH_PATH)),
+            .display_order(get_ordering(GUI_ARCHIVE_PATH)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(PLAYER)
+            .long("player")
+            .takes_value(true)
+            .env("VID_DUP_FINDER_PLAYER")
+            .help("For use in the gui: Program used to play videos (default: vlc). Playing two videos at the same time is only possible with vlc, so with any other player they are played one after the other")
+            .display_order(get_ordering(PLAYER)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(FILE_MANAGER)
+            .long("file-manager")
+            .takes_value(true)
+            .help("For use in the gui: Program used to show a video in its folder, which is given the path of the video (e.g. nautilus). By default the folder is opened with xdg-open on Linux, \"open -R\" on macOS and \"explorer /select,\" on Windows")
+            .display_order(get_ordering(FILE_MANAGER)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(RESULTS_FILE)
+            .long("results")
+            .takes_value(true)
+            .help("For use with --gui, --tui or --auto-resolve: Instead of searching, resolve the groups of duplicates in the given file, which must have been written by an earlier search with --json. The cache must contain the videos in the file, but the videos are not searched or rehashed")
+            .conflicts_with_all(&[FILE_PATHS, REF_PATHS, REF_SETS, UPDATE_CACHE_ONLY, CACHE_PLAN, INSPECT])
+            .display_order(get_ordering(RESULTS_FILE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(BASELINE)
+            .long("baseline")
+            .takes_value(true)
+            .help("Only report groups of duplicates which contain at least one file that is not in the given file of earlier results, which must have been written by an earlier search with --json-output (without --relative-to). Useful for finding only the duplicates which are new since the last search")
+            .conflicts_with(RESULTS_FILE)
+            .display_order(get_ordering(BASELINE)),
     );
 
     clap_app = clap_app.arg(
@@ -192,6 +590,15 @@ fn build_app() -> clap::App<'static, 'static> {
             .display_order(get_ordering(PRINT_UNIQUE)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(SORT_UNIQUE_BY)
+            .long("sort-unique-by")
+            .takes_value(true)
+            .requires(PRINT_UNIQUE)
+            .help("Sort unique videos by one of: path, size, duration, resolution, bitrate. Everything but path is sorted largest first. Combine with --json-rich to also print the stats of each video")
+            .display_order(get_ordering(SORT_UNIQUE_BY)),
+    );
+
     clap_app = clap_app.arg(
         clap::Arg::with_name(JSON_OUTPUT)
             .long("json-output")
@@ -199,6 +606,128 @@ fn build_app() -> clap::App<'static, 'static> {
             .display_order(get_ordering(JSON_OUTPUT)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(JSON_LINES)
+            .long("json-lines")
+            .help("Print outputs as newline delimited JSON, with one compact JSON object per group (or one path per line with --print-unique). Each line is written as soon as it is ready")
+            .conflicts_with(JSON_OUTPUT)
+            .conflicts_with(JSON_RICH)
+            .conflicts_with(DIR_SUMMARY)
+            .display_order(get_ordering(JSON_LINES)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(OUTPUT_FILE)
+            .long("output-file")
+            .takes_value(true)
+            .help("Write search results (in text or JSON format) to the given file instead of stdout. The file is overwritten if it already exists")
+            .display_order(get_ordering(OUTPUT_FILE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(JSON_RICH)
+            .long("json-rich")
+            .help("Print outputs in JSON format, with each file annotated with its duration, resolution, size, bitrate, audio and png_size. Each group also contains the largest distance between any two of its members")
+            .conflicts_with(JSON_OUTPUT)
+            .conflicts_with(DIR_SUMMARY)
+            .display_order(get_ordering(JSON_RICH)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(PRESERVE_INPUT_ORDER)
+            .long("preserve-input-order")
+            .help("Order groups of duplicates by the position of their earliest member in --files, so that output follows the order in which paths were given")
+            .display_order(get_ordering(PRESERVE_INPUT_ORDER)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(SORT_GROUPS_BY)
+            .long("sort")
+            .takes_value(true)
+            .possible_values(&["similarity", "size", "path", "group-size"])
+            .help("Order groups of duplicates (in the output and in the GUI) by one of: similarity (most similar first), size (the space that removing all but the largest file would free, most first), path (of the first file in each group), or group-size (largest groups first)")
+            .conflicts_with(PRESERVE_INPUT_ORDER)
+            .display_order(get_ordering(SORT_GROUPS_BY)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(LADDER_ORDER)
+            .long("ladder-order")
+            .help("Within each group, list duplicates from the highest resolution to the lowest. References are still listed first. Applies to all outputs, including match thumbnails and the GUI")
+            .display_order(get_ordering(LADDER_ORDER)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(MAX_GROUPS)
+            .long("max-groups")
+            .alias("stop-after")
+            .takes_value(true)
+            .help("Only report the first N groups of duplicates. This does not make the search any faster: the whole search is performed and its results are then truncated, so that the output, the GUI or --match-thumbnails-dir only deal with N groups")
+            .conflicts_with(PRINT_UNIQUE)
+            .display_order(get_ordering(MAX_GROUPS)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(FUZZY_RENAME)
+            .long("fuzzy-rename")
+            .help("Label each group of duplicates as either \"rename\" (the files have identical contents, so they are the same file under different names) or \"near-duplicate\" (the files look the same but are different files). The contents of files are only read for groups which look identical")
+            .conflicts_with(GUI)
+            .display_order(get_ordering(FUZZY_RENAME)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(DIR_SUMMARY)
+            .long("dir-summary")
+            .help("Instead of printing groups of duplicates, print each directory containing duplicates together with the number and total size of the duplicates in it, most duplicates first. In each group, the reference (or the largest file, if there is no reference) is not counted as a duplicate. The summary is written to --output-file if given")
+            .conflicts_with(GUI)
+            .conflicts_with(PRINT_UNIQUE)
+            .display_order(get_ordering(DIR_SUMMARY)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(STATS)
+            .long("stats")
+            .help("At the end of the run, print how many files were found and hashed, how many could not be hashed, how many groups of duplicates were found, and how much space removing all but the largest file of each group would free. This is always printed with --verbose")
+            .conflicts_with(VERBOSITY_QUIET)
+            .display_order(get_ordering(STATS)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(RELATIVE_TO)
+            .long("relative-to")
+            .takes_value(true)
+            .help("Print every path in the results relative to the given directory, in both text and json output. Paths outside of this directory are printed in full. Only affects what is printed, and not which files are searched")
+            .display_order(get_ordering(RELATIVE_TO)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(EXPORT_UNIQUE_TO)
+            .long("export-unique-to")
+            .takes_value(true)
+            .help("Copy one video from each group of duplicates (the one which would be kept when resolving the group, as chosen by --protect and --prefer-subtitles, then by resolution, bitrate and size), and every unique video, into the given directory. The full path of each video is recreated inside the directory unless --export-flatten is given. Nothing is removed from the searched directories")
+            .conflicts_with(GUI)
+            .conflicts_with(TUI)
+            .conflicts_with(UPDATE_CACHE_ONLY)
+            .conflicts_with(OUTPUT_THUMBS_DIR)
+            .display_order(get_ordering(EXPORT_UNIQUE_TO)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(EXPORT_FLATTEN)
+            .long("export-flatten")
+            .requires(EXPORT_UNIQUE_TO)
+            .help("Put all exported videos directly in the export directory. If two videos have the same name, a suffix such as \" (1)\" is added")
+            .display_order(get_ordering(EXPORT_FLATTEN)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(EXPORT_HARDLINK)
+            .long("export-hardlink")
+            .requires(EXPORT_UNIQUE_TO)
+            .help("Hard link exported videos instead of copying them. Videos which can't be hard linked (e.g. because they are on another filesystem) are copied")
+            .display_order(get_ordering(EXPORT_HARDLINK)),
+    );
+
     clap_app = clap_app.arg(
         clap::Arg::with_name(OUTPUT_THUMBS_DIR)
             .long("match-thumbnails-dir")
@@ -207,6 +736,39 @@ fn build_app() -> clap::App<'static, 'static> {
             .display_order(get_ordering(OUTPUT_THUMBS_DIR)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(THUMBS_CONCURRENCY)
+            .long("match-thumbnails-concurrency")
+            .takes_value(true)
+            .help("The maximum number of match thumbnails written at once (default: the number of logical cores). Each thumbnail is built in memory before it is written, so lower this if writing thumbnails runs out of memory")
+            .requires(OUTPUT_THUMBS_DIR)
+            .display_order(get_ordering(THUMBS_CONCURRENCY)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(THUMB_SIZE)
+            .long("thumb-size")
+            .takes_value(true)
+            .help("The width and height in pixels of each frame in match thumbnails (default 200). With --gui this also sets the initial zoom level")
+            .display_order(get_ordering(THUMB_SIZE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(THUMB_FRAMES)
+            .long("thumb-frames")
+            .takes_value(true)
+            .help("The number of frames of each video in match thumbnails (default 7)")
+            .display_order(get_ordering(THUMB_FRAMES)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(THUMB_FPS)
+            .long("thumb-fps")
+            .takes_value(true)
+            .help("The rate that frames are taken from each video in match thumbnails, in frames per second. Either a number or a fraction, e.g. 2 or 1/5 (default 1/5, one frame every five seconds)")
+            .display_order(get_ordering(THUMB_FPS)),
+    );
+
     clap_app = clap_app.arg(
         clap::Arg::with_name(TOLERANCE)
             .long("tolerance")
@@ -215,6 +777,115 @@ fn build_app() -> clap::App<'static, 'static> {
             .display_order(get_ordering(TOLERANCE)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(AUTO_TOLERANCE)
+            .long("auto-tolerance")
+            .help("Do not print search results. Instead, search at several different tolerances and print how many duplicates are found at each one, to help choose a value for --tolerance")
+            .conflicts_with(GUI)
+            .conflicts_with(UPDATE_CACHE_ONLY)
+            .display_order(get_ordering(AUTO_TOLERANCE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(ROBUSTNESS_CHECK)
+            .long("robustness-check")
+            .takes_value(true)
+            .help("Do not run a search. Instead create copies of the given video file that are scaled down, re-encoded at a low bitrate, and letterboxed, then print how different each copy is from the original and whether --tolerance would match it. Copies are made of the whole file, so this may take a while for long videos")
+            .conflicts_with_all(&[FILE_PATHS, REF_PATHS, REF_SETS, GUI, UPDATE_CACHE_ONLY])
+            .display_order(get_ordering(ROBUSTNESS_CHECK)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(MULTI_REF)
+            .long("multi-ref")
+            .help("Normally a file from --files is only reported against the first reference it matches. With this flag it is reported against every reference it matches, so it may appear in several groups. This can make the output much larger, and means the output can no longer be used as a list of files to delete. Each reference is searched against every file from --files separately, so the search takes time proportional to the number of references multiplied by the number of files, and uses memory for one copy of the hashes from --files per thread")
+            .requires(REF_PATHS)
+            .display_order(get_ordering(MULTI_REF)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(PREFILTER_DURATION)
+            .long("prefilter-duration")
+            .takes_value(true)
+            .help("Before searching, split videos into groups of similar duration and only compare videos within the same group. Videos are never compared if the shorter one is more than the given fraction (a number between 0.0 and 1.0, e.g. 0.05) shorter than the longer one. Videos whose duration is unknown are still compared with every video. This speeds up searches of large collections, but videos which have been trimmed will not be found")
+            .display_order(get_ordering(PREFILTER_DURATION)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(MIN_GROUP_SIZE)
+            .long("min-group-size")
+            .takes_value(true)
+            .help("Only report groups of at least N videos (including the reference, if there is one). Videos in smaller groups are treated as unique")
+            .display_order(get_ordering(MIN_GROUP_SIZE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(TRANSITIVE)
+            .long("transitive")
+            .help("Merge groups which share a video, so that if A matches B and B matches C then A, B and C are reported as one group, even if A and C are too different to match each other. Cannot be used with --with-refs")
+            .conflicts_with_all(&[REF_PATHS, REF_SETS])
+            .display_order(get_ordering(TRANSITIVE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(VERIFY_IDENTICAL)
+            .long("verify-identical")
+            .help("For groups whose videos look exactly the same (a distance of 0), also compare the contents of the files. If the contents are not all identical then the group is split into files with the same contents, and files whose contents match no other file are not reported (or offered for deletion) and are listed in a warning instead. This is slow, because every file in these groups is read in full")
+            .display_order(get_ordering(VERIFY_IDENTICAL)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(EXACT_FIRST)
+            .long("exact-first")
+            .help("Before searching, find files whose contents are byte for byte identical and report them as separate groups labeled \"exact\". Only one copy of each such file is searched, which speeds up searches of folders with many identical copies (such as backups). Only files with the same size as another file are read")
+            .display_order(get_ordering(EXACT_FIRST)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(AFFIRM_AUDIO)
+            .long("affirm-audio")
+            .help("After searching, also compare a fingerprint of the audio of the videos in each group, and only report videos whose audio matches too. This separates videos which look the same but have different audio, such as different dubs. Videos without audio are not checked. The first time a video is checked this is slow, because its audio must be decoded. Fingerprints are stored beside the cache, and reused until the video is modified")
+            .display_order(get_ordering(AFFIRM_AUDIO)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(PREFER_SUBTITLES)
+            .long("prefer-subtitles")
+            .help("When choosing which video in a group to keep, prefer videos with subtitle (or attachment) streams over videos without, before comparing resolution, bitrate and size")
+            .display_order(get_ordering(PREFER_SUBTITLES)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(REQUIRE_KEEPER_READABLE)
+            .long("require-keeper-readable")
+            .help("When resolving a group of duplicates, first check that the video to keep can actually be read (for example, that it is not on a drive that has been unmounted). If it cannot, then nothing in the group is trashed")
+            .display_order(get_ordering(REQUIRE_KEEPER_READABLE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(REQUIRE_AUDIO)
+            .long("require-audio")
+            .help("Only search files in --files that have an audio track")
+            .conflicts_with(EXCLUDE_AUDIO)
+            .display_order(get_ordering(REQUIRE_AUDIO)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(EXCLUDE_AUDIO)
+            .long("exclude-audio")
+            .help("Only search files in --files that do not have an audio track")
+            .conflicts_with(REQUIRE_AUDIO)
+            .display_order(get_ordering(EXCLUDE_AUDIO)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(MIN_RESOLUTION)
+            .long("min-resolution")
+            .takes_value(true)
+            .help("Only search files in --files with at least the given resolution, given as WIDTHxHEIGHT, e.g. 640x360. Videos in portrait orientation are also accepted if they meet the rotated resolution")
+            .display_order(get_ordering(MIN_RESOLUTION)),
+    );
+
     clap_app = clap_app.arg(
         clap::Arg::with_name(NO_UPDATE_CACHE)
             .long("no-update-cache")
@@ -222,6 +893,47 @@ fn build_app() -> clap::App<'static, 'static> {
             .display_order(get_ordering(NO_UPDATE_CACHE)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(RESUME)
+            .long("resume")
+            .help("If a previous cache update was interrupted, then update the cache using the files that it found, instead of searching --files and --with-refs again. The update is only resumed if --files and --with-refs are the same as those of the interrupted update. Files added since the interrupted update will not be found. Files already in the cache are not hashed again, so any interrupted update can be continued by simply running it again, but this skips the (possibly slow) search for files")
+            .conflicts_with(NO_UPDATE_CACHE)
+            .display_order(get_ordering(RESUME)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(CACHE_FLUSH_INTERVAL)
+            .long("cache-flush-interval")
+            .takes_value(true)
+            .help("While updating the cache, save it each time this many new files have been hashed (default 100). If the update is stopped with Ctrl-C, the cache is also saved before exiting")
+            .conflicts_with(NO_UPDATE_CACHE)
+            .display_order(get_ordering(CACHE_FLUSH_INTERVAL)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(INVALIDATE_ON_FFMPEG_CHANGE)
+            .long("invalidate-cache-on-ffmpeg-change")
+            .help("If the cache was created with a different version of ffmpeg, then delete it and rebuild it from scratch. (By default, a warning is printed instead)")
+            .conflicts_with(NO_UPDATE_CACHE)
+            .display_order(get_ordering(INVALIDATE_ON_FFMPEG_CHANGE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(FFMPEG_NICE)
+            .long("ffmpeg-nice")
+            .takes_value(true)
+            .help("Run at a reduced scheduling priority (a niceness from 0 to 19) so that background runs do not starve other programs. The priority applies to the whole vid_dup_finder process, and to every ffmpeg and ffprobe process that it starts. It is ignored with --gui (unless with --update-cache-only), so that the GUI stays responsive. Has no effect on non-unix platforms")
+            .display_order(get_ordering(FFMPEG_NICE)),
+    );
+
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(THREADS)
+            .long("threads")
+            .takes_value(true)
+            .help("The number of threads used to hash and search videos (default: the number of logical cores). Each thread runs its own ffmpeg process while hashing, so a lower number can be faster on slow disks. With '--threads 1' videos are hashed one at a time")
+            .display_order(get_ordering(THREADS)),
+    );
+
     clap_app = clap_app.arg(
         clap::Arg::with_name(ARGS_FILE)
             .long("args-file")
@@ -247,6 +959,14 @@ fn build_app() -> clap::App<'static, 'static> {
             .display_order(get_ordering(VERBOSITY_VERBOSE)),
     );
 
+    clap_app = clap_app.arg(
+        clap::Arg::with_name(DRY_RUN)
+            .long("dry-run")
+            .help("Do not write anything to disk. The cache is not updated (as with --no-update-cache), and instead of writing match thumbnails, exporting files or resolving duplicates, print what would have been done")
+            .conflicts_with(UPDATE_CACHE_ONLY)
+            .display_order(get_ordering(DRY_RUN)),
+    );
+
     clap_app
 }
 
@@ -259,7 +979,7 @@ pub(crate) fn parse_args() -> Result<AppCfg, AppError> {
     //take the arguments from the file instead.
     let args = get_args_from_cmdline_or_file()?;
 
-    let file_paths = match args.values_of_os(FILE_PATHS) {
+    let mut file_paths = match args.values_of_os(FILE_PATHS) {
         Some(paths) => paths
             .into_iter()
             .map(|p| absolutify_path(&cwd, p.as_ref()))
@@ -267,13 +987,87 @@ pub(crate) fn parse_args() -> Result<AppCfg, AppError> {
         None => vec![],
     };
 
+    //Listed paths which don't exist are only reported, so that one stale line doesn't stop a search of
+    //thousands of files.
+    let mut missing_file_paths = vec![];
+    if let Some(files_from) = args.value_of_os(FILES_FROM) {
+        //The TUI reads its keypresses from stdin, so stdin can't also be used for the list of paths.
+        let files_from_bytes = if files_from == "-" {
+            if args.is_present(TUI) {
+                return Err(FilesFromStdinWithTui);
+            }
+            let mut files_from_bytes = vec![];
+            std::io::stdin()
+                .read_to_end(&mut files_from_bytes)
+                .map_err(|e| FilesFromReadError(PathBuf::from(files_from), e))?;
+            files_from_bytes
+        } else {
+            let files_from_path = absolutify_path(&cwd, files_from.as_ref());
+            std::fs::read(&files_from_path).map_err(|e| FilesFromReadError(files_from_path, e))?
+        };
+
+        for line in files_from_bytes
+            .split(|b| *b == b'\n')
+            .map(strip_carriage_return)
+        {
+            if line.is_empty() {
+                continue;
+            }
+            let path = absolutify_path(&cwd, &path_from_bytes(line));
+            if path.exists() {
+                file_paths.push(path);
+            } else {
+                missing_file_paths.push(path);
+            }
+        }
+    }
+
+    let ref_sets = match args.values_of(REF_SETS) {
+        Some(ref_sets) => ref_sets
+            .map(|ref_set| match ref_set.split_once(':') {
+                Some((name, path)) if !name.is_empty() && !path.is_empty() => Ok(RefSet {
+                    name: name.to_string(),
+                    path: absolutify_path(&cwd, path.as_ref()),
+                }),
+                _ => Err(ParseRefSet(ref_set.to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![],
+    };
+
+    //The paths of any reference sets are searched in the same way as --with-refs.
     let ref_file_paths = match args.values_of_os(REF_PATHS) {
         Some(ref_file_dirs) => ref_file_dirs
             .map(|p| absolutify_path(&cwd, p.as_ref()))
             .collect(),
+        None => ref_sets
+            .iter()
+            .map(|ref_set| ref_set.path.clone())
+            .collect(),
+    };
+
+    let protect_dirs = match args.values_of_os(PROTECT_PATHS) {
+        Some(protect_dirs) => protect_dirs
+            .map(|p| absolutify_path(&cwd, p.as_ref()))
+            .collect(),
         None => vec![],
     };
 
+    let keep_list = match args.value_of_os(KEEP_LIST) {
+        Some(keep_list_path) => {
+            let keep_list_path = absolutify_path(&cwd, keep_list_path.as_ref());
+            let keep_list_text = std::fs::read_to_string(&keep_list_path)
+                .map_err(|e| KeepListReadError(keep_list_path, e))?;
+            keep_list_text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| absolutify_path(&cwd, line.as_ref()))
+                .collect()
+        }
+        None => HashSet::new(),
+    };
+
     let exclude_file_paths = match args.values_of_os(EXCL_FILE_PATHS) {
         Some(exclude_file_paths) => exclude_file_paths
             .map(|p| absolutify_path(&cwd, p.as_ref()))
@@ -281,16 +1075,140 @@ pub(crate) fn parse_args() -> Result<AppCfg, AppError> {
         None => vec![],
     };
 
-    let excl_exts = args
-        .values_of_os(EXCL_EXTS)
-        .unwrap()
-        .map(&OsStr::to_owned)
-        .collect();
+    let mut excl_regexes = vec![];
+    for pattern in args.values_of(EXCL_REGEX).into_iter().flatten() {
+        match regex::Regex::new(pattern) {
+            Ok(excl_regex) => excl_regexes.push(excl_regex),
+            Err(e) => return Err(ParseExclRegex(pattern.to_string(), e)),
+        }
+    }
+
+    let parse_size = |arg_name| match args.value_of(arg_name) {
+        Some(value) => match byte_unit::Byte::from_str(value) {
+            Ok(size) => Ok(Some(size.get_bytes() as u64)),
+            Err(_e) => Err(ParseFileSize(value.to_string())),
+        },
+        None => Ok(None),
+    };
+    let min_size = parse_size(MIN_SIZE)?;
+    let max_size = parse_size(MAX_SIZE)?;
+
+    let incl_exts = args.values_of_os(INCL_EXTS).map(|incl_exts| {
+        incl_exts
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .collect::<Vec<_>>()
+    });
+
+    //Extensions are either whitelisted or blacklisted, so the default exclusions only apply when no
+    //whitelist is given.
+    let excl_exts = match args.values_of_os(EXCL_EXTS) {
+        Some(excl_exts) => excl_exts.map(&OsStr::to_owned).collect(),
+        None if incl_exts.is_some() => vec![],
+        None => DEFAULT_EXCL_EXTS.iter().map(OsString::from).collect(),
+    };
+
+    let thumbs_concurrency = match args.value_of(THUMBS_CONCURRENCY) {
+        Some(value) => match value.parse() {
+            Ok(concurrency) if concurrency > 0 => Some(concurrency),
+            _ => return Err(ParseThumbsConcurrency(value.to_string())),
+        },
+        None => None,
+    };
 
     let output_thumbs_dir = args
         .value_of_os(OUTPUT_THUMBS_DIR)
         .map(|p| absolutify_path(&cwd, p.as_ref()));
 
+    let output_file = args
+        .value_of_os(OUTPUT_FILE)
+        .map(|p| absolutify_path(&cwd, p.as_ref()));
+
+    let relative_to = args
+        .value_of_os(RELATIVE_TO)
+        .map(|p| absolutify_path(&cwd, p.as_ref()));
+
+    let export_cfg = args.value_of_os(EXPORT_UNIQUE_TO).map(|p| ExportCfg {
+        export_dir: absolutify_path(&cwd, p.as_ref()),
+        flatten: args.is_present(EXPORT_FLATTEN),
+        hardlink: args.is_present(EXPORT_HARDLINK),
+    });
+
+    let sort_unique_by = match args.value_of(SORT_UNIQUE_BY) {
+        Some("path") => Some(UniqueSortKey::Path),
+        Some("size") => Some(UniqueSortKey::Size),
+        Some("duration") => Some(UniqueSortKey::Duration),
+        Some("resolution") => Some(UniqueSortKey::Resolution),
+        Some("bitrate") => Some(UniqueSortKey::Bitrate),
+        Some(value) => return Err(ParseSortUniqueBy(value.to_string())),
+        None => None,
+    };
+
+    let sort_groups_by = match args.value_of(SORT_GROUPS_BY) {
+        Some("similarity") => Some(GroupSortKey::Similarity),
+        Some("size") => Some(GroupSortKey::Size),
+        Some("path") => Some(GroupSortKey::Path),
+        Some("group-size") => Some(GroupSortKey::GroupSize),
+        Some(value) => return Err(ParseSortGroupsBy(value.to_string())),
+        None => None,
+    };
+
+    let auto_resolve = match args.value_of(AUTO_RESOLVE) {
+        Some("largest-res") => Some(AutoResolveMetric::LargestRes),
+        Some("largest-file") => Some(AutoResolveMetric::LargestFile),
+        Some("largest-pngsize") => Some(AutoResolveMetric::LargestPngsize),
+        Some("highest-bitrate") => Some(AutoResolveMetric::HighestBitrate),
+        Some(value) => return Err(ParseAutoResolve(value.to_string())),
+        None => None,
+    };
+
+    let thumb_size = match args.value_of(THUMB_SIZE) {
+        Some(value) => match value.parse() {
+            Ok(thumb_size) if thumb_size > 0 => Some(thumb_size),
+            _ => return Err(ParseThumbSize(value.to_string())),
+        },
+        None => None,
+    };
+
+    let thumb_frames = match args.value_of(THUMB_FRAMES) {
+        Some(value) => match value.parse() {
+            Ok(thumb_frames) if thumb_frames > 0 => Some(thumb_frames),
+            _ => return Err(ParseThumbFrames(value.to_string())),
+        },
+        None => None,
+    };
+
+    //The frame rate is passed straight to ffmpeg, so check it here to get a useful error message.
+    let thumb_fps = match args.value_of(THUMB_FPS) {
+        Some(value) => {
+            let is_positive = |s: &str| matches!(s.parse::<u32>(), Ok(n) if n > 0);
+            let is_valid = match value.split_once('/') {
+                Some((num, den)) => is_positive(num) && is_positive(den),
+                None => is_positive(value),
+            };
+            if !is_valid {
+                return Err(ParseThumbFps(value.to_string()));
+            }
+            Some(value.to_string())
+        }
+        None => None,
+    };
+
+    let max_groups = match args.value_of(MAX_GROUPS) {
+        Some(value) => match value.parse() {
+            Ok(max_groups) if max_groups > 0 => Some(max_groups),
+            _ => return Err(ParseMaxGroups(value.to_string())),
+        },
+        None => None,
+    };
+
+    let min_group_size = match args.value_of(MIN_GROUP_SIZE) {
+        Some(value) => match value.parse() {
+            Ok(min_group_size) if min_group_size >= 2 => Some(min_group_size),
+            _ => return Err(ParseMinGroupSize(value.to_string())),
+        },
+        None => None,
+    };
+
     let tolerance = match args.value_of(TOLERANCE) {
         Some(value) => match value.parse() {
             Ok(value) => NormalizedTolerance::new(value),
@@ -299,16 +1217,93 @@ pub(crate) fn parse_args() -> Result<AppCfg, AppError> {
         None => NormalizedTolerance::default(),
     };
 
+    let prefilter_duration = match args.value_of(PREFILTER_DURATION) {
+        Some(value) => match value.parse() {
+            Ok(ratio) if ratio > 0.0 && ratio < 1.0 => Some(ratio),
+            _ => return Err(ParsePrefilterDuration(value.to_string())),
+        },
+        None => None,
+    };
+
+    let ffmpeg_nice = match args.value_of(FFMPEG_NICE) {
+        Some(value) => match value.parse() {
+            Ok(niceness) if (0..=19).contains(&niceness) => Some(niceness),
+            _ => return Err(ParseFfmpegNice(value.to_string())),
+        },
+        None => None,
+    };
+
+    let threads = match args.value_of(THREADS) {
+        Some(value) => match value.parse() {
+            Ok(threads) if threads > 0 => Some(threads),
+            _ => return Err(ParseThreads(value.to_string())),
+        },
+        None => None,
+    };
+
+    let flush_interval = match args.value_of(CACHE_FLUSH_INTERVAL) {
+        Some(value) => match value.parse() {
+            Ok(interval) if interval > 0 => Some(interval),
+            _ => return Err(ParseCacheFlushInterval(value.to_string())),
+        },
+        None => None,
+    };
+
     let cache_cfg = CacheCfg {
         cache_path: args.value_of_os(CACHE_FILE).map(PathBuf::from),
-        no_update_cache: args.is_present(NO_UPDATE_CACHE),
+        no_update_cache: args.is_present(NO_UPDATE_CACHE)
+            || args.is_present(DRY_RUN)
+            || args.is_present(DUMP_CACHE_JSON),
+        resume: args.is_present(RESUME),
+        prune: args.is_present(PRUNE_CACHE),
+        compact: args.is_present(COMPACT_CACHE),
+        discard_non_videos: args.is_present(DISCARD_NON_VIDEOS),
+        flush_interval,
+        wait_for_lock: args.is_present(WAIT_FOR_LOCK),
+        invalidate_on_ffmpeg_change: args.is_present(INVALIDATE_ON_FFMPEG_CHANGE),
     };
 
     let dir_cfg = DirCfg {
         cand_dirs: file_paths,
+        missing_cand_paths: missing_file_paths,
         ref_dirs: ref_file_paths,
+        ref_sets,
+        protect_dirs,
+        keep_list,
         excl_dirs: exclude_file_paths,
+        excl_regexes,
+        min_size,
+        max_size,
         excl_exts,
+        incl_exts,
+        no_recurse: args.is_present(NO_RECURSE),
+        follow_symlinks: args.is_present(FOLLOW_SYMLINKS),
+    };
+
+    let audio = if args.is_present(REQUIRE_AUDIO) {
+        AudioFilter::Require
+    } else if args.is_present(EXCLUDE_AUDIO) {
+        AudioFilter::Exclude
+    } else {
+        AudioFilter::Any
+    };
+
+    let min_resolution = match args.value_of(MIN_RESOLUTION) {
+        Some(value) => {
+            let parsed = value
+                .split_once('x')
+                .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+            match parsed {
+                Some(min_resolution) => Some(min_resolution),
+                None => return Err(ParseMinResolution(value.to_string())),
+            }
+        }
+        None => None,
+    };
+
+    let cand_filter_cfg = CandFilterCfg {
+        audio,
+        min_resolution,
     };
 
     let verbosity = if args.is_present(VERBOSITY_QUIET) {
@@ -322,23 +1317,102 @@ pub(crate) fn parse_args() -> Result<AppCfg, AppError> {
     let output_cfg = OutputCfg {
         print_unique: args.is_present(PRINT_UNIQUE),
         print_duplicates: !args.is_present(PRINT_UNIQUE),
+        sort_unique_by,
         json_output: args.is_present(JSON_OUTPUT),
+        json_lines: args.is_present(JSON_LINES),
+        json_rich: args.is_present(JSON_RICH),
+        classify_renames: args.is_present(FUZZY_RENAME),
+        preserve_input_order: args.is_present(PRESERVE_INPUT_ORDER),
+        sort_groups_by,
+        ladder_order: args.is_present(LADDER_ORDER),
+        max_groups,
+        dir_summary: args.is_present(DIR_SUMMARY),
+        print_stats: args.is_present(STATS),
+        relative_to,
         output_thumbs_dir,
+        output_file,
+        thumbs_concurrency,
+        thumb_size,
+        thumb_frames,
+        thumb_fps,
 
         verbosity,
         gui: args.is_present(GUI),
-        gui_trash_path: args.value_of_os(GUI_TRASH_PATH).map(PathBuf::from),
+        tui: args.is_present(TUI),
+        auto_resolve,
+        move_dups_to: args
+            .value_of_os(MOVE_DUPS_TO)
+            .map(|p| absolutify_path(&cwd, p.as_ref())),
+        gui_trash_path: args
+            .value_of_os(GUI_TRASH_PATH)
+            .map(|p| absolutify_path(&cwd, p.as_ref())),
+        gui_archive_path: args
+            .value_of_os(GUI_ARCHIVE_PATH)
+            .map(|p| absolutify_path(&cwd, p.as_ref())),
+        player: args.value_of(PLAYER).map(str::to_string),
+        file_manager: args.value_of(FILE_MANAGER).map(str::to_string),
     };
 
     let ret = AppCfg {
         cache_cfg,
         output_cfg,
+        export_cfg,
         dir_cfg,
+        cand_filter_cfg,
 
         update_cache_only: args.is_present(UPDATE_CACHE_ONLY),
+        cache_plan: args.is_present(CACHE_PLAN),
+        inspect: args
+            .value_of_os(INSPECT)
+            .map(|p| absolutify_path(&cwd, p.as_ref())),
+        dump_cache_json: args
+            .value_of_os(DUMP_CACHE_JSON)
+            .map(|p| absolutify_path(&cwd, p.as_ref())),
+        explain_match: args.values_of_os(EXPLAIN_MATCH).map(|mut paths| {
+            let mut next_path = || absolutify_path(&cwd, paths.next().unwrap_or_default().as_ref());
+            (next_path(), next_path())
+        }),
+        compare: args.values_of_os(COMPARE).map(|mut paths| {
+            let mut next_path = || absolutify_path(&cwd, paths.next().unwrap_or_default().as_ref());
+            (next_path(), next_path())
+        }),
+        tune_tolerance: args
+            .value_of_os(TUNE_TOLERANCE)
+            .map(|p| absolutify_path(&cwd, p.as_ref())),
+        ffmpeg_nice,
+        threads,
         tolerance,
+        auto_tolerance: args.is_present(AUTO_TOLERANCE),
+        robustness_check: args
+            .value_of_os(ROBUSTNESS_CHECK)
+            .map(|p| absolutify_path(&cwd, p.as_ref())),
+        multi_ref: args.is_present(MULTI_REF),
+        prefilter_duration,
+        min_group_size,
+        transitive: args.is_present(TRANSITIVE),
+        verify_identical: args.is_present(VERIFY_IDENTICAL),
+        exact_first: args.is_present(EXACT_FIRST),
+        affirm_audio: args.is_present(AFFIRM_AUDIO),
+        prefer_subtitles: args.is_present(PREFER_SUBTITLES),
+        require_keeper_readable: args.is_present(REQUIRE_KEEPER_READABLE),
+        dry_run: args.is_present(DRY_RUN),
+        baseline: args
+            .value_of_os(BASELINE)
+            .map(|p| absolutify_path(&cwd, p.as_ref())),
+        results_file: args
+            .value_of_os(RESULTS_FILE)
+            .map(|p| absolutify_path(&cwd, p.as_ref())),
     };
 
+    //Saved results can only be resolved, so there is nothing to do unless a way of resolving them was chosen.
+    if ret.results_file.is_some()
+        && !ret.output_cfg.gui
+        && !ret.output_cfg.tui
+        && ret.output_cfg.auto_resolve.is_none()
+    {
+        return Err(ResultsWithoutResolver);
+    }
+
     Ok(ret)
 }
 
@@ -386,6 +1460,27 @@ fn parse_argsfile_args(argsfile_text: &str) -> Result<clap::ArgMatches<'static>,
     Ok(matches)
 }
 
+//Filenames may start or end with spaces, so lines of --files-from are not trimmed. Only the '\r' of a
+//windows line ending is removed.
+fn strip_carriage_return(line: &[u8]) -> &[u8] {
+    match line {
+        [rest @ .., b'\r'] => rest,
+        _ => line,
+    }
+}
+
+//Paths on unix can be any bytes, so lines of --files-from are used as they are instead of being decoded as UTF-8.
+#[cfg(target_family = "unix")]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(target_family = "unix"))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
 fn absolutify_path(cwd: &Path, path: &Path) -> PathBuf {
     //get the absolute path if it is not absolute, by prepending the cwd.
     let path = if path.is_relative() {
@@ -0,0 +1,232 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+//Audio is decoded to mono at a low sample rate, and only the start of each file is fingerprinted.
+const SAMPLE_RATE: usize = 8000;
+const FINGERPRINT_SECS: u32 = 120;
+const WINDOW_SAMPLES: usize = SAMPLE_RATE / 10;
+
+//Fingerprints are compared at small offsets from each other, to allow for a few frames having been
+//trimmed from the start of one copy.
+const MAX_SHIFT: usize = 5;
+
+//The largest fraction of differing bits for two fingerprints to match. Unrelated audio differs in about
+//half of its bits.
+const MATCH_THRESHOLD: f64 = 0.25;
+
+//A fingerprint of the loudness of a video's audio over time. Each bit records whether the audio got louder
+//or quieter between two consecutive tenths of a second. Different dubs of the same video have very different
+//loudness patterns, even though the videos look the same.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioHash {
+    bits: Vec<bool>,
+}
+
+impl AudioHash {
+    pub fn from_file(src_path: &Path) -> Option<Self> {
+        let output = Command::new("ffmpeg")
+            .args(["-v", "error", "-i"])
+            .arg(src_path)
+            .args(["-t", &FINGERPRINT_SECS.to_string()])
+            .args(["-vn", "-ac", "1", "-ar", &SAMPLE_RATE.to_string()])
+            .args(["-f", "s16le", "-"])
+            .output();
+
+        let output = match output {
+            Ok(output) if output.status.success() => output,
+            Ok(output) => {
+                warn!(
+                    "Failed to read audio of {}: ffmpeg exited with {}",
+                    src_path.display(),
+                    output.status
+                );
+                return None;
+            }
+            Err(e) => {
+                warn!("Failed to read audio of {}: {}", src_path.display(), e);
+                return None;
+            }
+        };
+
+        let samples = output
+            .stdout
+            .chunks_exact(2)
+            .map(|bytes| f64::from(i16::from_le_bytes([bytes[0], bytes[1]])))
+            .collect::<Vec<_>>();
+
+        let energies = samples
+            .chunks_exact(WINDOW_SAMPLES)
+            .map(|window| window.iter().map(|sample| sample * sample).sum::<f64>())
+            .collect::<Vec<_>>();
+
+        let bits = energies
+            .windows(2)
+            .map(|pair| pair[1] > pair[0])
+            .collect::<Vec<_>>();
+
+        if bits.is_empty() {
+            None
+        } else {
+            Some(Self { bits })
+        }
+    }
+
+    //The smallest fraction of differing bits over every allowed offset between the two fingerprints.
+    pub fn distance(&self, other: &Self) -> f64 {
+        let shifted_distance = |a: &[bool], b: &[bool]| {
+            let overlap = a.len().min(b.len());
+            let differing = a.iter().zip(b).filter(|(x, y)| x != y).count();
+            differing as f64 / overlap as f64
+        };
+
+        (0..=MAX_SHIFT)
+            .flat_map(|shift| {
+                vec![
+                    (self.bits.get(shift..), Some(&other.bits[..])),
+                    (Some(&self.bits[..]), other.bits.get(shift..)),
+                ]
+            })
+            .filter_map(|(a, b)| match (a, b) {
+                (Some(a), Some(b)) if !a.is_empty() && !b.is_empty() => {
+                    Some(shifted_distance(a, b))
+                }
+                _ => None,
+            })
+            .fold(1.0, f64::min)
+    }
+
+    pub fn matches(&self, other: &Self) -> bool {
+        self.distance(other) <= MATCH_THRESHOLD
+    }
+}
+
+//A fingerprint along with the modification time of the file when it was fingerprinted.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredAudioHash {
+    mtime: SystemTime,
+    hash: AudioHash,
+}
+
+//Fingerprints are stored in a small file beside the cache, so that each file's audio is only decoded once.
+fn audio_hash_file_path(cache_path: &Path) -> PathBuf {
+    let mut file_name = cache_path
+        .file_name()
+        .map(OsString::from)
+        .unwrap_or_default();
+    file_name.push(".audio_hashes");
+    cache_path.with_file_name(file_name)
+}
+
+fn modified_time(src_path: &Path) -> Option<SystemTime> {
+    fs::metadata(src_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn load_stored_hashes(audio_hash_path: &Path) -> HashMap<PathBuf, StoredAudioHash> {
+    let contents = match fs::read(audio_hash_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return HashMap::new(),
+        Err(e) => {
+            warn!(
+                "Failed to read audio fingerprints at {}: {}",
+                audio_hash_path.display(),
+                e
+            );
+            return HashMap::new();
+        }
+    };
+
+    match bincode::deserialize(&contents) {
+        Ok(stored) => stored,
+        Err(e) => {
+            warn!(
+                "Ignoring unreadable audio fingerprints at {}: {}",
+                audio_hash_path.display(),
+                e
+            );
+            HashMap::new()
+        }
+    }
+}
+
+//Fingerprint the audio of the given files. Fingerprints stored beside the cache are reused if the file has not
+//been modified since, and any new fingerprints are stored unless the cache is read-only. Files whose audio can't
+//be read have no fingerprint.
+pub fn audio_hashes(
+    cache_path: &Path,
+    src_paths: HashSet<PathBuf>,
+    read_only: bool,
+) -> HashMap<PathBuf, AudioHash> {
+    let audio_hash_path = audio_hash_file_path(cache_path);
+    let mut stored = load_stored_hashes(&audio_hash_path);
+
+    let (reused, to_hash): (Vec<_>, Vec<_>) = src_paths
+        .into_iter()
+        .map(|src_path| {
+            let mtime = modified_time(&src_path);
+            (src_path, mtime)
+        })
+        .partition(|(src_path, mtime)| match (stored.get(src_path), mtime) {
+            (Some(stored_hash), Some(mtime)) => stored_hash.mtime == *mtime,
+            _ => false,
+        });
+
+    let new_hashes = to_hash
+        .into_par_iter()
+        .filter_map(|(src_path, mtime)| {
+            let hash = AudioHash::from_file(&src_path)?;
+            Some((src_path, mtime, hash))
+        })
+        .collect::<Vec<_>>();
+
+    let mut hashes = reused
+        .into_iter()
+        .map(|(src_path, _mtime)| {
+            let hash = stored[&src_path].hash.clone();
+            (src_path, hash)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let num_new = new_hashes.len();
+    for (src_path, mtime, hash) in new_hashes {
+        if let Some(mtime) = mtime {
+            stored.insert(
+                src_path.clone(),
+                StoredAudioHash {
+                    mtime,
+                    hash: hash.clone(),
+                },
+            );
+        }
+        hashes.insert(src_path, hash);
+    }
+
+    if !read_only && num_new > 0 {
+        //Fingerprints of files which no longer exist will never be used again.
+        stored.retain(|src_path, _stored_hash| src_path.exists());
+
+        let result = bincode::serialize(&stored)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| fs::write(&audio_hash_path, contents).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!(
+                "Failed to store audio fingerprints at {}: {}",
+                audio_hash_path.display(),
+                e
+            );
+        }
+    }
+
+    hashes
+}
@@ -0,0 +1,56 @@
+use crate::app::*;
+
+//Resolve every group of duplicates without asking, by keeping the video which is best by the given metric and
+//trashing the rest. Groups where no single video is best are skipped, so nothing is trashed arbitrarily.
+pub fn run_auto_resolve(thunks: Vec<ResolutionThunk>, metric: AutoResolveMetric) {
+    let num_thunks = thunks.len();
+    let mut num_resolved = 0;
+    for (i, thunk) in thunks.iter().enumerate() {
+        let winner_idx = match clear_winner_idx(thunk, metric) {
+            Some(winner_idx) => winner_idx,
+            None => {
+                println!(
+                    "Group {} of {}: skipping, as no video is best by {}",
+                    i + 1,
+                    num_thunks,
+                    metric.label()
+                );
+                continue;
+            }
+        };
+
+        let entries = thunk.entries();
+        println!(
+            "Group {} of {}: keeping {}",
+            i + 1,
+            num_thunks,
+            entries[winner_idx].display()
+        );
+        match thunk.resolve(&winner_idx.to_string()) {
+            Ok(()) => num_resolved += 1,
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    info!("Resolved {} of {} groups", num_resolved, num_thunks);
+}
+
+//The index of the only video in the group that wins by the given metric, if exactly one does. Protected videos
+//(from --protect or --keep-list) are always kept, so if there are any then the winner is chosen from them alone.
+fn clear_winner_idx(thunk: &ResolutionThunk, metric: AutoResolveMetric) -> Option<usize> {
+    let candidate_idxs = thunk.keeper_candidate_idxs();
+    let best_value = candidate_idxs
+        .iter()
+        .map(|&idx| thunk.metric_value(idx, metric))
+        .max()?;
+
+    let winner_idxs = candidate_idxs
+        .into_iter()
+        .filter(|&idx| thunk.metric_value(idx, metric) == best_value)
+        .collect::<Vec<_>>();
+
+    match winner_idxs.as_slice() {
+        [winner_idx] => Some(*winner_idx),
+        _ => None,
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::app::*;
+
+//An advisory lock on the cache file, held for as long as this value is alive. Two instances saving the
+//same cache at once can interleave and lose entries, so an instance that will write to the cache takes
+//this lock first. The lock is released when the lock file is closed, including if the process dies.
+pub struct CacheLock {
+    _lock_file: File,
+}
+
+fn lock_path(cache_path: &Path) -> PathBuf {
+    let mut lock_path = cache_path.as_os_str().to_os_string();
+    lock_path.push(".lock");
+    PathBuf::from(lock_path)
+}
+
+//Lock the cache at the given path. If another instance holds the lock, then either fail or wait for it
+//to be released.
+pub fn lock_cache(cache_path: &Path, wait: bool) -> Result<CacheLock, AppError> {
+    let lock_path = lock_path(cache_path);
+    let lock_err = |e| AppError::CacheLockError(lock_path.clone(), e);
+
+    let lock_file = File::create(&lock_path).map_err(lock_err)?;
+
+    match try_lock(&lock_file) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+            if !wait {
+                return Err(AppError::CacheLocked(cache_path.to_path_buf()));
+            }
+            info!(
+                "Waiting for another instance to finish with the cache at {}",
+                cache_path.display()
+            );
+            lock(&lock_file).map_err(lock_err)?;
+        }
+        Err(e) => return Err(lock_err(e)),
+    }
+
+    Ok(CacheLock {
+        _lock_file: lock_file,
+    })
+}
+
+#[cfg(target_family = "unix")]
+fn flock(file: &File, operation: libc::c_int) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        match unsafe { libc::flock(file.as_raw_fd(), operation) } {
+            0 => return Ok(()),
+            _ => {
+                let e = std::io::Error::last_os_error();
+                if e.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_family = "unix")]
+fn try_lock(file: &File) -> std::io::Result<()> {
+    flock(file, libc::LOCK_EX | libc::LOCK_NB)
+}
+
+#[cfg(target_family = "unix")]
+fn lock(file: &File) -> std::io::Result<()> {
+    flock(file, libc::LOCK_EX)
+}
+
+//The cache is only locked once per run, so this warning is only given once.
+#[cfg(not(target_family = "unix"))]
+fn try_lock(_file: &File) -> std::io::Result<()> {
+    warn!("Locking the cache is only supported on unix. Other instances using the same cache will not be detected.");
+    Ok(())
+}
+
+#[cfg(not(target_family = "unix"))]
+fn lock(_file: &File) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn a_locked_cache_cannot_be_locked_again() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache_path = tempdir.path().join("cache.bin");
+
+        let first_lock = lock_cache(&cache_path, false).unwrap();
+        assert!(matches!(
+            lock_cache(&cache_path, false),
+            Err(AppError::CacheLocked(_))
+        ));
+
+        drop(first_lock);
+        assert!(lock_cache(&cache_path, false).is_ok());
+    }
+}
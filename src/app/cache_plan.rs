@@ -0,0 +1,32 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+//Print how many files an update of the cache would hash, rehash and remove, without doing any of it.
+//
+//The cache does not expose the modification times it recorded for each file, so instead a cached file
+//is counted as possibly stale if it was modified after the cache file was last saved.
+pub fn print_cache_plan(on_disk: &HashSet<PathBuf>, cached: &HashSet<PathBuf>, cache_path: &Path) {
+    let cache_modified = modified(cache_path);
+
+    let new = on_disk.difference(cached).count();
+    let to_prune = cached.difference(on_disk).count();
+
+    let (possibly_stale, unchanged): (Vec<&PathBuf>, Vec<&PathBuf>) = on_disk
+        .intersection(cached)
+        .partition(|path| match (modified(path), cache_modified) {
+            (Some(file_modified), Some(cache_modified)) => file_modified > cache_modified,
+            _ => true,
+        });
+
+    println!("new:            {}", new);
+    println!("possibly stale: {}", possibly_stale.len());
+    println!("unchanged:      {}", unchanged.len());
+    println!("to prune:       {}", to_prune);
+}
@@ -0,0 +1,13 @@
+use std::{io::Read, path::Path};
+
+use sha2::Digest;
+
+pub fn sha256_reader(mut reader: impl Read) -> std::io::Result<[u8; 32]> {
+    let mut hasher = sha2::Sha256::new();
+    std::io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+pub fn sha256_file(path: &Path) -> std::io::Result<[u8; 32]> {
+    sha256_reader(std::fs::File::open(path)?)
+}
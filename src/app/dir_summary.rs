@@ -0,0 +1,81 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use serde_json::json;
+use video_hash_filesystem_cache::*;
+
+use crate::app::{resolution_plan, *};
+
+#[derive(Debug, Default, Serialize)]
+struct DirSummary {
+    duplicates: u64,
+    size: u64,
+}
+
+pub fn print_dir_summary(
+    search_output: &SearchOutput,
+    cache: &VideoHashFilesystemCache,
+    keeper_rules: &KeeperRules,
+    output_cfg: &OutputCfg,
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
+    let mut summaries: HashMap<&Path, DirSummary> = HashMap::new();
+
+    for group in search_output.dup_groups() {
+        //Count the files which would be removed if the group were deduplicated.
+        let (_to_keep, to_trash) = resolution_plan::split_group(group, cache, keeper_rules);
+        for dup_path in to_trash {
+            let dir = dup_path.parent().unwrap_or(dup_path);
+            let summary = summaries.entry(dir).or_default();
+            summary.duplicates += 1;
+            summary.size += cache
+                .fetch_stats(dup_path)
+                .map(|stats| stats.size())
+                .unwrap_or_default();
+        }
+    }
+
+    let mut summaries = summaries.into_iter().collect::<Vec<_>>();
+    summaries.sort_by(|(dir_a, a), (dir_b, b)| {
+        (b.duplicates, b.size, dir_a).cmp(&(a.duplicates, a.size, dir_b))
+    });
+
+    if output_cfg.json_output {
+        #[derive(Serialize)]
+        struct JsonStruct<'a> {
+            directory: &'a Path,
+            #[serde(flatten)]
+            summary: &'a DirSummary,
+        }
+
+        let output_vec = summaries
+            .iter()
+            .map(|(directory, summary)| JsonStruct {
+                directory: output_cfg.output_path(directory),
+                summary,
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::to_writer_pretty(&mut *out, &json!(output_vec))?;
+        writeln!(out)?;
+    } else {
+        writeln!(out, "{:>10} {:>12}  directory", "duplicates", "size")?;
+        for (dir, summary) in summaries {
+            let size =
+                byte_unit::Byte::from_bytes(summary.size as u128).get_appropriate_unit(false);
+            writeln!(
+                out,
+                "{:>10} {:>12}  {}",
+                summary.duplicates,
+                size.to_string(),
+                PathBuf::from(output_cfg.output_path(dir)).display()
+            )?;
+        }
+    }
+
+    Ok(())
+}
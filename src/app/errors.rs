@@ -5,6 +5,12 @@ use vid_dup_finder_lib::*;
 
 use video_hash_filesystem_cache::*;
 
+use crate::app::file_ops::TrashError;
+
+//All errors produced by the application. Errors from the library crates and from std are wrapped rather than
+//converted to strings: the message of each variant describes only what the application was doing, and the
+//underlying error is available through Error::source, so that the whole chain can be printed without
+//repeating any part of it.
 #[derive(Error, Debug)]
 pub enum AppError {
     /////////////////////////////////
@@ -18,6 +24,71 @@ pub enum AppError {
     #[error("could not parse provided spatial tolerance: {0}")]
     ParseTolerance(String),
 
+    #[error("could not parse provided duration prefilter. Expected a number between 0.0 and 1.0, got: {0}")]
+    ParsePrefilterDuration(String),
+
+    #[error("could not parse provided file size. Expected a size such as 100MB, got: {0}")]
+    ParseFileSize(String),
+
+    #[error("could not parse provided exclude regex: {0}")]
+    ParseExclRegex(String, #[source] regex::Error),
+
+    #[error("could not parse provided cache flush interval. Expected a positive number, got: {0}")]
+    ParseCacheFlushInterval(String),
+
+    #[error("could not parse provided ffmpeg niceness. Expected a number from 0 to 19, got: {0}")]
+    ParseFfmpegNice(String),
+
+    #[error("could not parse provided number of threads. Expected a positive number, got: {0}")]
+    ParseThreads(String),
+
+    #[error("could not parse provided number of groups. Expected a positive number, got: {0}")]
+    ParseMaxGroups(String),
+
+    #[error(
+        "could not parse provided minimum group size. Expected a number of at least 2, got: {0}"
+    )]
+    ParseMinGroupSize(String),
+
+    #[error(
+        "could not parse provided number of match images. Expected a positive number, got: {0}"
+    )]
+    ParseThumbsConcurrency(String),
+
+    #[error(
+        "could not parse provided thumbnail size. Expected a positive number of pixels, got: {0}"
+    )]
+    ParseThumbSize(String),
+
+    #[error(
+        "could not parse provided number of thumbnail frames. Expected a positive number, got: {0}"
+    )]
+    ParseThumbFrames(String),
+
+    #[error("could not parse provided thumbnail frame rate. Expected a number of frames per second such as 2 or 1/5, got: {0}")]
+    ParseThumbFps(String),
+
+    #[error("could not parse provided sort order. Expected one of path, size, duration, resolution or bitrate, got: {0}")]
+    ParseSortUniqueBy(String),
+
+    #[error("could not parse provided group sort order. Expected one of similarity, size, path or group-size, got: {0}")]
+    ParseSortGroupsBy(String),
+
+    #[error("could not parse provided auto-resolve metric. Expected one of largest-res, largest-file, largest-pngsize or highest-bitrate, got: {0}")]
+    ParseAutoResolve(String),
+
+    #[error("could not parse provided minimum resolution. Expected WIDTHxHEIGHT, got: {0}")]
+    ParseMinResolution(String),
+
+    #[error("could not parse provided reference set. Expected NAME:PATH, got: {0}")]
+    ParseRefSet(String),
+
+    #[error("--results can only be used with --gui, --tui or --auto-resolve")]
+    ResultsWithoutResolver,
+
+    #[error("--files-from - reads paths from stdin, so it cannot be used with --tui. Write the paths to a file and give that to --files-from instead")]
+    FilesFromStdinWithTui,
+
     /////////////////////////////////
     //Impossible combination of --files, --with-refs --exclude given.
     //It's important to get the wording of these right because these errors
@@ -50,21 +121,77 @@ pub enum AppError {
 
     /////////////////////////////////
     //Other file projection problems
-    #[error("Video file search error, at path: {1}")]
-    FileSearchError(PathBuf, walkdir::Error),
+    #[error("Video file search error, at path: {0}")]
+    FileSearchError(PathBuf, #[source] walkdir::Error),
 
     /////////////////////////////////
     //hash cache problems
     #[error(transparent)]
     CacheErrror(#[from] VdfCacheError),
 
-    #[error("Hash Creation Error: {0}")]
+    #[error("Could not find a location for the cache file. Use --cache-file to choose one")]
+    NoCachePath,
+
+    #[error("Cannot write the cache file to {0}. Use --cache-file to store it somewhere else")]
+    CacheDirNotWritable(PathBuf, #[source] std::io::Error),
+
+    #[error("The cache file at {0} is being used by another instance. Use --wait-for-lock to wait for it to finish")]
+    CacheLocked(PathBuf),
+
+    #[error("Failed to write search results to {0}")]
+    OutputFileError(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to write search results")]
+    OutputError(#[source] std::io::Error),
+
+    #[error("Failed to lock the cache file using {0}")]
+    CacheLockError(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to remove outdated cache file at {0}")]
+    CacheInvalidateError(PathBuf, #[source] std::io::Error),
+
+    #[error(
+        "No hash could be created for {0}. It may not be a video, or it may be too short to hash"
+    )]
+    NoHashError(PathBuf, #[source] VdfCacheError),
+
+    #[error("Failed to read paths to search from {0}")]
+    FilesFromReadError(PathBuf, #[source] std::io::Error),
+
+    #[error("Path in --files-from not found: {0}")]
+    FilesFromPathNotFoundError(PathBuf),
+
+    #[error("Failed to read keep list at {0}")]
+    KeepListReadError(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to write cache JSON to {0}")]
+    DumpCacheJsonError(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to read results file at {0}")]
+    ResultsFileReadError(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to parse results file at {0}. Results files are written with --json")]
+    ResultsFileParseError(PathBuf, #[source] serde_json::Error),
+
+    #[error("Failed to read labels file at {0}")]
+    LabelsFileReadError(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to parse labels file at {0}, line {1}. Expected two paths and a label of dup or not, separated by commas, got: {2}")]
+    LabelsFileParseError(PathBuf, usize, String),
+
+    #[error("I/O error in robustness check working directory {0}")]
+    RobustnessCheckIoError(PathBuf, #[source] std::io::Error),
+
+    #[error("Hash Creation Error")]
     CreateHashError(#[from] HashCreationErrorKind),
 
+    #[error("Failed to export {0}")]
+    ExportError(PathBuf, #[source] TrashError),
+
     /////////////////////////////////
     //gui
     #[error("Failed to start the GUI")]
-    #[allow(dead_code)] // variant is unused when gui is not compiled
+    #[cfg(all(target_family = "unix", feature = "gui"))]
     GuiStartError,
 
     #[error(
@@ -82,6 +209,19 @@ pub enum AppError {
 }
 
 impl AppError {
+    //The message of this error followed by the messages of all of its sources, on one line.
+    pub fn display_chain(&self) -> String {
+        let mut ret = self.to_string();
+        let mut source: Option<&(dyn std::error::Error + 'static)> =
+            std::error::Error::source(self);
+        while let Some(e) = source {
+            ret.push_str(": ");
+            ret.push_str(&e.to_string());
+            source = e.source();
+        }
+        ret
+    }
+
     pub fn from_cand_exclusion_error(e: FileProjectionError) -> Self {
         match e {
             FileProjectionError::SrcPathExcluded { src_path, excl_path } => Self::SrcPathExcludedError {src_path, excl_path},
@@ -0,0 +1,86 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use rayon::prelude::*;
+
+use super::{content_hash, search_output::group_paths};
+use crate::app::*;
+
+//Groups of files with identical contents, found by --exact-first before the search.
+pub struct ExactGroups {
+    pub groups: Vec<DupGroup>,
+
+    //Candidates which are already reported in an exact group, and so are left out of the search. One member of
+    //each group without a reference is still searched, so that near-duplicates of it are found as usual.
+    pub excluded: HashSet<PathBuf>,
+}
+
+//Group the candidates (and references) whose contents are byte for byte identical. Only files which have the same
+//size as another file are read. As in a search, references are never grouped with each other, and a group with a
+//reference uses the first of them (by path) as its reference.
+pub fn find_exact_groups(cand_paths: &[&Path], ref_paths: &[&Path]) -> ExactGroups {
+    let ref_set = ref_paths.iter().copied().collect::<HashSet<_>>();
+
+    let mut by_size: HashMap<u64, Vec<&Path>> = HashMap::new();
+    for src_path in cand_paths.iter().chain(ref_paths.iter()) {
+        if let Ok(metadata) = std::fs::metadata(src_path) {
+            by_size.entry(metadata.len()).or_default().push(src_path);
+        }
+    }
+
+    let same_size_paths = by_size
+        .into_values()
+        .filter(|paths| paths.len() >= 2 && paths.iter().any(|path| !ref_set.contains(path)))
+        .flatten()
+        .collect::<Vec<_>>();
+
+    let mut by_digest: HashMap<[u8; 32], Vec<&Path>> = HashMap::new();
+    for (src_path, digest) in same_size_paths
+        .into_par_iter()
+        .filter_map(|src_path| match content_hash::sha256_file(src_path) {
+            Ok(digest) => Some((src_path, digest)),
+            Err(e) => {
+                warn!("Failed to read {}: {}", src_path.display(), e);
+                None
+            }
+        })
+        .collect::<Vec<_>>()
+    {
+        by_digest.entry(digest).or_default().push(src_path);
+    }
+
+    let mut exact_groups = ExactGroups {
+        groups: vec![],
+        excluded: HashSet::new(),
+    };
+    for mut paths in by_digest.into_values() {
+        paths.sort();
+        let (refs, cands): (Vec<&Path>, Vec<&Path>) =
+            paths.into_iter().partition(|path| ref_set.contains(path));
+
+        let reference = refs.first().copied();
+        if cands.is_empty() || (reference.is_none() && cands.len() < 2) {
+            continue;
+        }
+
+        exact_groups.excluded.extend(
+            cands
+                .iter()
+                .skip(if reference.is_some() { 0 } else { 1 })
+                .map(|path| path.to_path_buf()),
+        );
+        exact_groups.groups.push(DupGroup::exact(
+            reference.map(Path::to_path_buf),
+            cands.into_iter().map(Path::to_path_buf).collect(),
+        ));
+    }
+
+    //Groups are found in no particular order, so order them by their first member.
+    exact_groups
+        .groups
+        .sort_by(|a, b| group_paths(a).next().cmp(&group_paths(b).next()));
+
+    exact_groups
+}
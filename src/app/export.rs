@@ -0,0 +1,74 @@
+use std::path::{Component, Path, PathBuf};
+
+use video_hash_filesystem_cache::*;
+
+use crate::app::{file_ops, resolution_plan, *};
+
+//Copy one file from each group of duplicates (the one that would be kept when resolving the group) and every
+//unique file into the export directory, producing a deduplicated copy of the search.
+pub fn export_unique(
+    search_output: &SearchOutput,
+    unique_paths: &[&Path],
+    cache: &VideoHashFilesystemCache,
+    keeper_rules: &KeeperRules,
+    export_cfg: &ExportCfg,
+    dry_run: bool,
+) -> Vec<AppError> {
+    let keepers = search_output.dup_groups().filter_map(|group| {
+        let (to_keep, _to_trash) = resolution_plan::split_group(group, cache, keeper_rules);
+        to_keep.first().copied()
+    });
+
+    let mut errs = vec![];
+    let mut num_exported = 0;
+    for src_path in keepers.chain(unique_paths.iter().copied()) {
+        let dest_path = export_path(src_path, export_cfg);
+        if dry_run {
+            println!(
+                "Would export {} to {}",
+                src_path.display(),
+                dest_path.display()
+            );
+            continue;
+        }
+
+        trace!(
+            "Exporting {} to {}",
+            src_path.display(),
+            dest_path.display()
+        );
+        match file_ops::copy_path(src_path, &dest_path, export_cfg.hardlink) {
+            Ok(()) => num_exported += 1,
+            Err(e) => errs.push(AppError::ExportError(src_path.to_path_buf(), e)),
+        }
+    }
+
+    if dry_run {
+        return errs;
+    }
+
+    info!(
+        "Exported {} files to {}",
+        num_exported,
+        export_cfg.export_dir.display()
+    );
+
+    errs
+}
+
+//Unless the export is flattened, the whole absolute path of each file is recreated under the export
+//directory (in the same way as the trash), so that files from different --files or --with-refs paths
+//cannot collide.
+fn export_path(src_path: &Path, export_cfg: &ExportCfg) -> PathBuf {
+    if export_cfg.flatten {
+        export_cfg
+            .export_dir
+            .join(src_path.file_name().unwrap_or_default())
+    } else {
+        let relative_path = src_path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect::<PathBuf>();
+        export_cfg.export_dir.join(relative_path)
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::app::*;
+
+//The first line of "ffmpeg -version", e.g. "ffmpeg version 4.3.2-0+deb11u2 Copyright (c) 2000-2021 the FFmpeg developers"
+pub fn ffmpeg_version() -> Option<String> {
+    let output = Command::new("ffmpeg").arg("-version").output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().next().map(|line| line.trim().to_string())
+}
+
+//The oldest version of ffmpeg that results are known to be good with. Older versions detect letterboxing
+//differently, which changes hashes.
+const MIN_FFMPEG_VERSION: FfmpegVersion = FfmpegVersion { major: 4, minor: 0 };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FfmpegVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl FfmpegVersion {
+    //Parse the version number from the first line of "ffmpeg -version". Release builds report versions like
+    //"4.3.2-0+deb11u2", "n6.0" or "5.1.2-static". Builds from git report e.g. "N-109465-g1234abcd", which
+    //has no version number, so None is returned.
+    pub fn parse(version_line: &str) -> Option<Self> {
+        let version = version_line
+            .strip_prefix("ffmpeg version ")?
+            .split_whitespace()
+            .next()?;
+        let version = version.strip_prefix('n').unwrap_or(version);
+
+        let mut numbers = version
+            .split(|c: char| !c.is_ascii_digit())
+            .map(str::parse::<u32>);
+        let major = numbers.next()?.ok()?;
+        let minor = numbers.next().and_then(Result::ok).unwrap_or(0);
+
+        Some(Self { major, minor })
+    }
+}
+
+impl std::fmt::Display for FfmpegVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+//Warn if the version of ffmpeg is older than the oldest known-good version.
+pub fn check_ffmpeg_version(version_line: &str) {
+    match FfmpegVersion::parse(version_line) {
+        Some(version) if version < MIN_FFMPEG_VERSION => warn!(
+            "ffmpeg {} is older than {}, so results may be different to those of newer versions. Version: {}",
+            version, MIN_FFMPEG_VERSION, version_line
+        ),
+        Some(_version) => (),
+        None => debug!("Could not parse ffmpeg version: {}", version_line),
+    }
+}
+
+//The version of ffmpeg that created the cache is stored in a small file beside it.
+fn version_file_path(cache_path: &Path) -> PathBuf {
+    let mut file_name = cache_path
+        .file_name()
+        .map(OsString::from)
+        .unwrap_or_default();
+    file_name.push(".ffmpeg_version");
+    cache_path.with_file_name(file_name)
+}
+
+//Compare the version of ffmpeg that created the cache with the current version. If they differ
+//then warn the user (results may change slightly between ffmpeg versions), or if requested, delete
+//the cache so that it is rebuilt with the current version.
+//
+//The version file is only written when the cache is new, so that the warning is repeated on each
+//run until the cache is rebuilt. If the cache is not going to be updated then nothing is written or deleted.
+pub fn check_cache_ffmpeg_version(
+    cache_path: &Path,
+    current_version: &str,
+    invalidate_on_change: bool,
+    read_only: bool,
+) -> Result<(), AppError> {
+    let version_path = version_file_path(cache_path);
+
+    let write_version = match fs::read_to_string(&version_path) {
+        Ok(cached_version) if cached_version.trim() == current_version => false,
+        Ok(cached_version) if invalidate_on_change && !read_only => {
+            info!(
+                "Cache was created with a different version of ffmpeg. Rebuilding cache. Cached version: {}, current version: {}",
+                cached_version.trim(),
+                current_version
+            );
+            match fs::remove_file(cache_path) {
+                Ok(()) => (),
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(AppError::CacheInvalidateError(cache_path.to_path_buf(), e)),
+            }
+            true
+        }
+        Ok(cached_version) => {
+            warn!(
+                "Cache was created with a different version of ffmpeg, so results may differ slightly from a fresh cache. Use --invalidate-cache-on-ffmpeg-change to rebuild it. Cached version: {}, current version: {}",
+                cached_version.trim(),
+                current_version
+            );
+            false
+        }
+
+        //Only record the version for new caches. If there is an existing cache without a version file then
+        //we don't know which version of ffmpeg created it.
+        Err(_) => !cache_path.exists(),
+    };
+
+    if write_version && !read_only {
+        if let Err(e) = fs::write(&version_path, current_version) {
+            warn!(
+                "Failed to record ffmpeg version at {}: {}",
+                version_path.display(),
+                e
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn version(major: u32, minor: u32) -> Option<FfmpegVersion> {
+        Some(FfmpegVersion { major, minor })
+    }
+
+    #[test]
+    fn release_versions_are_parsed() {
+        assert_eq!(
+            FfmpegVersion::parse(
+                "ffmpeg version 4.4.2-0ubuntu0.22.04.1 Copyright (c) 2000-2021 the FFmpeg developers"
+            ),
+            version(4, 4)
+        );
+        assert_eq!(
+            FfmpegVersion::parse(
+                "ffmpeg version 6.1.1 Copyright (c) 2000-2023 the FFmpeg developers"
+            ),
+            version(6, 1)
+        );
+        assert_eq!(
+            FfmpegVersion::parse(
+                "ffmpeg version n6.0 Copyright (c) 2000-2023 the FFmpeg developers"
+            ),
+            version(6, 0)
+        );
+        assert_eq!(
+            FfmpegVersion::parse(
+                "ffmpeg version n4.4-78-g031c0cb0b4 Copyright (c) 2000-2021 the FFmpeg developers"
+            ),
+            version(4, 4)
+        );
+        assert_eq!(
+            FfmpegVersion::parse(
+                "ffmpeg version 4.3.2-0+deb11u2 Copyright (c) 2000-2021 the FFmpeg developers"
+            ),
+            version(4, 3)
+        );
+    }
+
+    #[test]
+    fn git_builds_have_no_version() {
+        assert_eq!(
+            FfmpegVersion::parse(
+                "ffmpeg version N-109465-g1234abcd Copyright (c) 2000-2023 the FFmpeg developers"
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+use TrashError::*;
+
+use super::content_hash;
+
+#[derive(Error, Debug)]
+
+pub enum TrashError {
+    #[error("Failed to move file to the system trash: {0}")]
+    SystemTrashFailure(String, #[source] trash::Error),
+
+    #[error("Trash directory belongs to another user: {0}")]
+    TrashDirNotOwned(String),
+
+    #[error("Failed to open file at path {0}")]
+    FileOpenError(String, #[source] std::io::Error),
+
+    #[error("Failed to strip prefix '/' from path: {0}")]
+    StripPrefixError(#[from] std::path::StripPrefixError),
+
+    #[error("I/O Error at path {0}")]
+    IoError(String, #[source] std::io::Error),
+
+    #[error("Failed to delete file: {0}")]
+    DeleteFileFailure(String, #[source] std::io::Error),
+
+    #[error("Source file does not exist: {0}")]
+    SourceFileMissing(String),
+
+    #[error("Destination already exists: {0}")]
+    DestFileExists(String),
+
+    #[error("File to keep cannot be read, so its duplicates were not trashed: {0}")]
+    KeeperUnreadable(String, #[source] std::io::Error),
+
+    #[error("Failed to create parent directory for trash file: {0}")]
+    CreateParentDirFailure(String),
+
+    #[error("Coudn't extract parent directory from string: {0}")]
+    ExtractParentDirFailure(String),
+
+    #[error("move_path: Failed to copy file {0} to {1}")]
+    CopyFailError(String, String),
+
+    #[error("move_path: Unhandled error copying {0} to {1}")]
+    UnhandledError(String, String),
+
+    #[error("move_path: std::fs::rename returned None for moving {0} to {1}")]
+    RenameNoneError(String, String),
+}
+
+//The location in the trash directory that a file will be moved to. The whole absolute path of the
+//file is recreated under the trash directory.
+pub fn trash_path(trash_dir: &Path, p: &Path) -> Result<PathBuf, TrashError> {
+    check_trash_dir_owned(trash_dir)?;
+    let relative_filename = p.strip_prefix("/")?;
+    Ok(trash_dir.join(relative_filename))
+}
+
+//Files must never be moved somewhere that another user could read or delete them, so refuse to trash
+//into a directory owned by someone else. (If the directory does not exist yet, it will be created by
+//the current user)
+#[cfg(target_family = "unix")]
+fn check_trash_dir_owned(trash_dir: &Path) -> Result<(), TrashError> {
+    use std::os::unix::fs::MetadataExt;
+
+    match std::fs::metadata(trash_dir) {
+        Ok(metadata) if metadata.uid() != unsafe { libc::geteuid() } => Err(
+            TrashError::TrashDirNotOwned(trash_dir.to_string_lossy().to_string()),
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(target_family = "unix"))]
+fn check_trash_dir_owned(_trash_dir: &Path) -> Result<(), TrashError> {
+    Ok(())
+}
+
+//Move a file to the given location in the trash. If an identical copy of the file is already
+//there (possibly renamed with a suffix such as " (1)" because another file was already there when
+//it was trashed), then the file is deleted instead.
+//
+//With dry_run, the same checks are made but nothing on disk is changed. Instead the operation that would have
+//been performed is printed.
+pub fn trash_file(old_path: &Path, new_path: &Path, dry_run: bool) -> Result<(), TrashError> {
+    fn is_already_trashed(old_path: &Path, trash_path: &Path) -> Result<bool, TrashError> {
+        //If there is no file in the trash path (or any suffixed variant of it), then it is not already trashed.
+        let trashed_paths = existing_suffixed_variants(trash_path);
+        if trashed_paths.is_empty() {
+            return Ok(false);
+        }
+
+        fn sha2_file(path: &Path) -> Result<[u8; 32], TrashError> {
+            let file = match std::fs::File::open(&path) {
+                Ok(file) => Ok(file),
+                Err(e) => Err(TrashError::FileOpenError(
+                    path.to_string_lossy().to_string(),
+                    e,
+                )),
+            }?;
+
+            match content_hash::sha256_reader(file) {
+                Ok(digest) => Ok(digest),
+                Err(e) => Err(TrashError::IoError(path.to_string_lossy().to_string(), e)),
+            }
+        }
+
+        let old_digest = sha2_file(old_path)?;
+        for trashed_path in trashed_paths {
+            if old_digest == sha2_file(&trashed_path)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    if !dry_run {
+        println!("trashing {}", old_path.display());
+    }
+
+    match is_already_trashed(old_path, new_path)? {
+        true => delete_path(old_path, dry_run)?,
+        false => move_path(old_path, new_path, dry_run)?,
+    }
+
+    Ok(())
+}
+
+//Check that a file can actually be read, by reading its first byte. A file on an unmounted drive may still
+//be listed, so checking that it exists is not enough.
+pub fn check_readable(path: &Path) -> Result<(), TrashError> {
+    use std::io::Read;
+
+    let mut first_byte = [0; 1];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read(&mut first_byte))
+        .map_err(|e| KeeperUnreadable(path.to_string_lossy().to_string(), e))?;
+
+    Ok(())
+}
+
+//Used when no trash directory is given. The system trash keeps track of where each file came from, so it can
+//be restored from the file manager.
+pub fn system_trash_file(path: &Path, dry_run: bool) -> Result<(), TrashError> {
+    if !path.exists() {
+        return Err(SourceFileMissing(path.to_string_lossy().to_string()));
+    }
+
+    if dry_run {
+        println!("Would move {} to the system trash", path.display());
+        return Ok(());
+    }
+
+    println!("Moving {} to the system trash", path.display());
+    trash::delete(path).map_err(|e| SystemTrashFailure(path.to_string_lossy().to_string(), e))
+}
+
+pub fn delete_path(path: &Path, dry_run: bool) -> Result<(), TrashError> {
+    if dry_run {
+        println!("Would delete {}", path.display());
+        return Ok(());
+    }
+
+    println!("Deleting {}", path.display());
+
+    if let Err(e) = std::fs::remove_file(&path) {
+        let e = DeleteFileFailure(path.to_string_lossy().to_string(), e);
+        return Err(e);
+    };
+
+    Ok(())
+}
+
+//With dry_run, the destination (including any suffix added because it already exists) is printed, but the file
+//is not moved. When a resolution trashes a file and then moves another file to its name, the printed
+//destination will have a suffix that it would not really have, as the trashed file is still in place.
+pub fn move_path(source: &Path, dest: &Path, dry_run: bool) -> Result<(), TrashError> {
+    if !source.exists() {
+        return Err(SourceFileMissing(source.to_string_lossy().to_string()));
+    }
+
+    let dest = get_new_name_if_path_already_exists(dest);
+    if dry_run {
+        println!("Would move {} ------> {}", source.display(), dest.display());
+        return Ok(());
+    }
+
+    println!("Moving {} ------> {}", source.display(), dest.display());
+    create_parent_dir(&dest)?;
+
+    if let Err(e) = std::fs::rename(&source, &dest) {
+        match e.raw_os_error() {
+            Some(_) if is_cross_device_error(&e) => {
+                //try copy and delete.
+                println!("Unable to move. Performing copy and delete instead.");
+                if let Err(_e) = std::fs::copy(&source, &dest) {
+                    let e = CopyFailError(
+                        source.to_string_lossy().to_string(),
+                        dest.to_string_lossy().to_string(),
+                    );
+                    return Err(e);
+                };
+                delete_path(source, false)?;
+            }
+            Some(_) => {
+                let e = UnhandledError(
+                    source.to_string_lossy().to_string(),
+                    dest.to_string_lossy().to_string(),
+                );
+                return Err(e);
+            }
+            None => {
+                let e = RenameNoneError(
+                    source.to_string_lossy().to_string(),
+                    dest.to_string_lossy().to_string(),
+                );
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//Whether a rename failed because the destination is on another filesystem, in which case the file must be
+//copied and deleted instead.
+#[cfg(target_family = "unix")]
+fn is_cross_device_error(e: &std::io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::EPERM) | Some(libc::EXDEV))
+}
+
+#[cfg(target_family = "windows")]
+fn is_cross_device_error(e: &std::io::Error) -> bool {
+    const ERROR_NOT_SAME_DEVICE: i32 = 17;
+    e.raw_os_error() == Some(ERROR_NOT_SAME_DEVICE)
+}
+
+#[cfg(not(any(target_family = "unix", target_family = "windows")))]
+fn is_cross_device_error(_e: &std::io::Error) -> bool {
+    false
+}
+
+//Copy a file to a new location, leaving the original in place. If hardlink is set then a hard link is made instead,
+//falling back to a copy if that is not possible (e.g. because the destination is on another device).
+pub fn copy_path(source: &Path, dest: &Path, hardlink: bool) -> Result<(), TrashError> {
+    if !source.exists() {
+        return Err(SourceFileMissing(source.to_string_lossy().to_string()));
+    }
+
+    let dest = get_new_name_if_path_already_exists(dest);
+    create_parent_dir(&dest)?;
+
+    if hardlink {
+        match std::fs::hard_link(&source, &dest) {
+            Ok(()) => return Ok(()),
+            Err(e) => debug!(
+                "Unable to hard link {}: {}. Performing copy instead.",
+                source.display(),
+                e
+            ),
+        }
+    }
+
+    if let Err(_e) = std::fs::copy(&source, &dest) {
+        let e = CopyFailError(
+            source.to_string_lossy().to_string(),
+            dest.to_string_lossy().to_string(),
+        );
+        return Err(e);
+    };
+
+    Ok(())
+}
+
+fn create_parent_dir(dest: &Path) -> Result<(), TrashError> {
+    #[allow(clippy::question_mark)] //spurious
+    match dest.parent() {
+        Some(parent_dir) => {
+            if std::fs::create_dir_all(parent_dir).is_err() {
+                return Err(CreateParentDirFailure(
+                    parent_dir.to_string_lossy().to_string(),
+                ));
+            }
+        }
+        None => {
+            return Err(ExtractParentDirFailure(dest.to_string_lossy().to_string()));
+        }
+    };
+
+    Ok(())
+}
+
+//with a given path, check if it already exists on the filesystem.
+//If it does, append a suffix that does not exist (in the form "(1)" or "(2)" etc..)
+//until a filename is found that does exist. Then return the new name.
+pub fn get_new_name_if_path_already_exists(p: &Path) -> PathBuf {
+    path_and_suffixed_variants(p)
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
+
+//The given path, followed by the same path with the suffixes " (1)", " (2)" etc.. added to its file stem.
+fn path_and_suffixed_variants(p: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    let original_stem = p.file_stem().unwrap();
+    let extension = p.extension();
+
+    let suffixed_variants = (1u64..).map(move |counter| {
+        let mut new_file_stem = original_stem.to_os_string();
+        new_file_stem.push(OsString::from(format!(" ({})", counter)));
+
+        let mut ret = p.to_path_buf();
+        ret.set_file_name(new_file_stem);
+        if let Some(extension) = extension {
+            ret.set_extension(extension);
+        }
+        ret
+    });
+
+    std::iter::once(p.to_path_buf()).chain(suffixed_variants)
+}
+
+//The given path and every suffixed variant of it (as made by path_and_suffixed_variants) which exists. Variants
+//are created in order, but a gap is left whenever one of them is restored or deleted from the trash, so the
+//whole directory is searched instead of stopping at the first variant which does not exist.
+fn existing_suffixed_variants(p: &Path) -> Vec<PathBuf> {
+    let entries = match p.parent().map(std::fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return vec![],
+    };
+
+    let original_stem = p.file_stem().unwrap().to_string_lossy();
+    let is_suffixed_variant = |candidate: &Path| {
+        if candidate.extension() != p.extension() {
+            return false;
+        }
+
+        let candidate_stem = match candidate.file_stem() {
+            Some(candidate_stem) => candidate_stem.to_string_lossy(),
+            None => return false,
+        };
+        let counter = candidate_stem
+            .strip_prefix(original_stem.as_ref())
+            .and_then(|rest| rest.strip_prefix(" ("))
+            .and_then(|rest| rest.strip_suffix(')'));
+        match counter {
+            Some(counter) => !counter.is_empty() && counter.chars().all(|c| c.is_ascii_digit()),
+            None => false,
+        }
+    };
+
+    let mut variants = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|candidate| candidate == p || is_suffixed_variant(candidate))
+        .collect::<Vec<_>>();
+    variants.sort();
+    variants
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trashing_the_same_contents_twice_deletes_the_second_copy() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let src_path = tempdir.path().join("video.mp4");
+        let trash_path = tempdir.path().join("trash").join("video.mp4");
+
+        std::fs::write(&src_path, b"contents").unwrap();
+        trash_file(&src_path, &trash_path, false).unwrap();
+        assert!(trash_path.exists());
+
+        std::fs::write(&src_path, b"contents").unwrap();
+        trash_file(&src_path, &trash_path, false).unwrap();
+        assert!(!src_path.exists());
+        assert!(!tempdir.path().join("trash").join("video (1).mp4").exists());
+    }
+
+    #[test]
+    fn trashed_copies_are_found_after_a_gap_in_the_suffixes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let trash_dir = tempdir.path().join("trash");
+        std::fs::create_dir(&trash_dir).unwrap();
+        std::fs::write(trash_dir.join("video.mp4"), b"first").unwrap();
+        std::fs::write(trash_dir.join("video (2).mp4"), b"second").unwrap();
+
+        let src_path = tempdir.path().join("video.mp4");
+        std::fs::write(&src_path, b"second").unwrap();
+        trash_file(&src_path, &trash_dir.join("video.mp4"), false).unwrap();
+
+        assert!(!src_path.exists());
+        assert!(!trash_dir.join("video (1).mp4").exists());
+    }
+}
@@ -0,0 +1,118 @@
+use std::{ffi::OsString, path::Path, process::Command};
+
+use crate::app::*;
+
+const DEFAULT_PLAYER: &str = "vlc";
+
+//Programs started by the GUI to look at files outside of it.
+#[derive(Debug, Clone)]
+pub struct ExternalApps {
+    player: String,
+    file_manager: Option<String>,
+}
+
+impl ExternalApps {
+    pub fn from_cfg(output_cfg: &OutputCfg) -> Self {
+        Self {
+            player: output_cfg
+                .player
+                .clone()
+                .unwrap_or_else(|| DEFAULT_PLAYER.to_string()),
+            file_manager: output_cfg.file_manager.clone(),
+        }
+    }
+
+    //Play videos one after the other.
+    pub fn play(&self, paths: &[&Path]) {
+        let mut command = Command::new(&self.player);
+        command.args(paths);
+
+        if let Err(e) = command.spawn() {
+            warn!("Failed to start {}: {}", self.player, e);
+        }
+    }
+
+    //Play the first two videos at the same time, to compare them side by side. This relies on VLC's
+    //--input_slave, so other players play the videos one after the other instead.
+    pub fn play_simultaneously(&self, paths: &[&Path]) {
+        let (main_vid, follow_vid) = match paths {
+            [main_vid, follow_vid, ..] => (main_vid, follow_vid),
+            _ => return self.play(paths),
+        };
+
+        if !self.player_is_vlc() {
+            warn!(
+                "{} cannot play videos at the same time (only vlc can). Playing them one after the other instead",
+                self.player
+            );
+            return self.play(paths);
+        }
+
+        let mut follow_arg = OsString::from("--input_slave=");
+        follow_arg.push(follow_vid);
+        let mut command = Command::new(&self.player);
+        command.arg(main_vid).arg(&follow_arg);
+
+        if let Err(e) = command.spawn() {
+            warn!(
+                "Failed to start {} at {}: {}",
+                self.player,
+                follow_arg.to_string_lossy(),
+                e
+            );
+        }
+    }
+
+    fn player_is_vlc(&self) -> bool {
+        match Path::new(&self.player).file_stem() {
+            Some(stem) => stem.eq_ignore_ascii_case("vlc"),
+            None => false,
+        }
+    }
+
+    //Show the folder containing a file. A file manager given with --file-manager is passed the file itself, so
+    //that file managers which can select a file (e.g. nautilus) will do so. Otherwise the platform's own way of
+    //showing a file is used, which on Linux is xdg-open on the containing folder.
+    pub fn show_in_file_manager(&self, path: &Path) {
+        let mut command = match &self.file_manager {
+            Some(file_manager) => {
+                let mut command = Command::new(file_manager);
+                command.arg(path);
+                command
+            }
+            None => Self::default_file_manager_command(path),
+        };
+
+        if let Err(e) = command.spawn() {
+            warn!(
+                "Failed to start {} at {}: {}",
+                command.get_program().to_string_lossy(),
+                path.display(),
+                e
+            );
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn default_file_manager_command(path: &Path) -> Command {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        command
+    }
+
+    #[cfg(target_os = "windows")]
+    fn default_file_manager_command(path: &Path) -> Command {
+        let mut select_arg = std::ffi::OsString::from("/select,");
+        select_arg.push(path);
+        let mut command = Command::new("explorer");
+        command.arg(select_arg);
+        command
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    fn default_file_manager_command(path: &Path) -> Command {
+        let mut command = Command::new("xdg-open");
+        command.arg(path.parent().unwrap_or(path));
+        command
+    }
+}
@@ -1,7 +1,6 @@
 use std::{
     collections::{HashMap, HashSet},
-    ffi::OsString,
-    path::{Path, PathBuf},
+    path::PathBuf,
 };
 
 use gdk_pixbuf::Pixbuf;
@@ -9,7 +8,8 @@ use glib::clone;
 use gtk::{prelude::*, Button};
 
 use super::{
-    gui_thumbnail_set::{GuiThumbnailSet, ThumbChoice},
+    external_apps::ExternalApps,
+    gui_thumbnail_set::{GuiThumbnailSet, ThumbChoice, ThumbRowCache},
     gui_zoom::{ZoomState, ZoomValue},
 };
 use crate::app::*;
@@ -24,6 +24,8 @@ pub struct GuiEntryState {
     entry_idx: usize,
 
     excludes: HashSet<PathBuf>,
+
+    external_apps: ExternalApps,
 }
 
 impl GuiEntryState {
@@ -32,6 +34,8 @@ impl GuiEntryState {
         single_mode: bool,
         thumb_choice: ThumbChoice,
         zoom: ZoomState,
+        external_apps: ExternalApps,
+        row_cache: &ThumbRowCache,
     ) -> Self {
         let info = thunk
             .entries()
@@ -39,7 +43,7 @@ impl GuiEntryState {
             .map(|src_path| (src_path, thunk.hash(src_path)))
             .collect::<Vec<_>>();
 
-        let thumbs = GuiThumbnailSet::new(info, zoom, thumb_choice);
+        let thumbs = GuiThumbnailSet::new(info, zoom, thumb_choice, row_cache);
 
         let mut ret = Self {
             thumbs,
@@ -48,6 +52,7 @@ impl GuiEntryState {
             single_mode,
             entry_idx: 0,
             excludes: Default::default(),
+            external_apps,
         };
 
         ret.regen_thumbs_pixbuf();
@@ -166,6 +171,12 @@ impl GuiEntryState {
             "   "
         }));
 
+        let subtitles_label = gtk::Label::new(Some(if winning_stats.has_subtitles {
+            "SUB"
+        } else {
+            "   "
+        }));
+
         let duration = self.thunk.render_duration(src_path);
         let duration_label = gtk::Label::new(Some(&duration));
         duration_label.set_halign(gtk::Align::Start);
@@ -186,6 +197,7 @@ impl GuiEntryState {
         win_row.add(&res_label);
         win_row.add(&bitrate_label);
         win_row.add(&audio_label);
+        win_row.add(&subtitles_label);
         text_stack.add(&i_label);
         text_stack.add(&win_row);
         text_stack.add(&duration_label);
@@ -195,8 +207,9 @@ impl GuiEntryState {
         let button = Button::with_label(&src_path.to_string_lossy());
         button.set_halign(gtk::Align::Start);
         let src_path = src_path.to_path_buf();
+        let external_apps = self.external_apps.clone();
         button
-            .connect_clicked(clone!(@strong src_path => move |_|Self::vlc_video_inner(&src_path)));
+            .connect_clicked(clone!(@strong src_path => move |_| external_apps.play(&[&src_path])));
 
         let thumb = self.thumbs_pixbuf.as_ref().unwrap().get(&src_path).unwrap();
 
@@ -226,21 +239,21 @@ impl GuiEntryState {
         self.regen_thumbs_pixbuf();
     }
 
-    pub fn vlc_video(&self, idx: usize) {
+    pub fn play_video(&self, idx: usize) {
         if let Some(filename) = self.thunk.entries().get(idx) {
-            Self::vlc_video_inner(filename);
+            self.external_apps.play(&[filename]);
         }
     }
 
-    pub fn vlc_current_video(&self) {
+    pub fn play_current_video(&self) {
         if self.single_mode {
-            self.vlc_video(self.entry_idx);
+            self.play_video(self.entry_idx);
         }
     }
 
     pub fn nautilus_file(&self, idx: usize) {
         if let Some(filename) = self.thunk.entries().get(idx) {
-            Self::nautilus_file_inner(filename);
+            self.external_apps.show_in_file_manager(filename);
         }
     }
 
@@ -276,48 +289,20 @@ impl GuiEntryState {
         }
     }
 
-    pub fn vlc_all_slave(&self) {
-        let mut path_iter = self.thunk.entries().into_iter();
-
-        //let first_arg = shell_words::quote(&path_iter.next().unwrap()).to_string();
-        let main_vid = path_iter.next().unwrap();
-        let follow_vid = path_iter.next().unwrap();
-
-        let mut follow_arg = OsString::from("--input_slave=");
-        follow_arg.push(follow_vid);
-        let mut command = std::process::Command::new("vlc");
-        let command = command.arg(main_vid).arg(&follow_arg);
-
-        if let Err(e) = command.spawn() {
-            warn!(
-                "Failed to start vlc at {}: {}",
-                follow_arg.to_string_lossy(),
-                e
-            );
+    pub fn resolve_keep_best(&mut self) {
+        match self.thunk.best_entry_idx() {
+            Some(idx) => self.resolve(&idx.to_string()),
+            None => warn!("Could not choose a video to keep"),
         }
     }
 
-    pub fn vlc_all_seq(&self) {
-        let mut command = std::process::Command::new("vlc");
-        for entry in self.thunk.entries() {
-            command.arg(entry);
-        }
-
-        if let Err(e) = command.spawn() {
-            warn!("Failed to start vlc: {}", e);
-        }
+    pub fn play_all_simultaneously(&self) {
+        self.external_apps
+            .play_simultaneously(&self.thunk.entries());
     }
 
-    fn nautilus_file_inner(path: &Path) {
-        if let Err(e) = std::process::Command::new("nautilus").arg(path).spawn() {
-            warn!("Failed to start nautilus at {}: {}", path.display(), e);
-        }
-    }
-
-    fn vlc_video_inner(path: &Path) {
-        if let Err(e) = std::process::Command::new("vlc").arg(path).spawn() {
-            warn!("Failed to start vlc at {}: {}", path.display(), e);
-        }
+    pub fn play_all_seq(&self) {
+        self.external_apps.play(&self.thunk.entries());
     }
 
     fn regen_thumbs_pixbuf(&mut self) {
@@ -342,21 +327,43 @@ pub struct GuiState {
     zoom: ZoomState,
     thumb_choice: ThumbChoice,
     thunk_idx: usize,
-    current_thunk: GuiEntryState,
+    //None when there are no matches, in which case the GUI only shows a message.
+    current_thunk: Option<GuiEntryState>,
     keypress_state: KeypressState,
     keypress_string: String,
+    external_apps: ExternalApps,
+    //Outlives each GuiEntryState, so that returning to a group doesn't read its frames again.
+    row_cache: ThumbRowCache,
 }
 
 impl GuiState {
-    pub fn new(thunks: Vec<ResolutionThunk>, single_mode: bool) -> Self {
-        let default_zoom_state = ZoomState::new(50, 1000, 50, 50);
+    pub fn new(
+        thunks: Vec<ResolutionThunk>,
+        single_mode: bool,
+        thumb_size: Option<u32>,
+        external_apps: ExternalApps,
+    ) -> Self {
+        //Zoom levels must be multiples of 50. If the user asked for large thumbnails, then allow zooming
+        //in further than usual too.
+        let default_zoom_state = match thumb_size {
+            None => ZoomState::new(50, 1000, 50, 50),
+            Some(thumb_size) => {
+                let start = (thumb_size / 50).max(1) * 50;
+                ZoomState::new(50, (start * 2).max(1000), 50, start)
+            }
+        };
 
-        let current_entry = GuiEntryState::new(
-            thunks.get(0).unwrap().clone(),
-            single_mode,
-            ThumbChoice::Video,
-            default_zoom_state,
-        );
+        let row_cache = ThumbRowCache::default();
+        let current_entry = thunks.get(0).map(|thunk| {
+            GuiEntryState::new(
+                thunk.clone(),
+                single_mode,
+                ThumbChoice::Video,
+                default_zoom_state,
+                external_apps.clone(),
+                &row_cache,
+            )
+        });
 
         Self {
             thunks,
@@ -369,10 +376,20 @@ impl GuiState {
 
             keypress_state: KeypressState::None,
             keypress_string: "".to_string(),
+            external_apps,
+            row_cache,
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.thunks.is_empty()
+    }
+
     pub fn next_thunk(&mut self) {
+        if self.is_empty() {
+            return;
+        }
+
         if self.thunk_idx < self.thunks.len() - 1 {
             self.thunk_idx += 1;
         } else {
@@ -383,6 +400,10 @@ impl GuiState {
     }
 
     pub fn prev_thunk(&mut self) {
+        if self.is_empty() {
+            return;
+        }
+
         if self.thunk_idx > 0 {
             self.thunk_idx -= 1;
         } else {
@@ -401,23 +422,37 @@ impl GuiState {
         the_label.set_halign(gtk::Align::Start);
         b.add(&the_label);
 
-        let entries = self.current_thunk.render();
-        b.add(&entries);
+        match &self.current_thunk {
+            Some(current_thunk) => b.add(&current_thunk.render()),
+            None => {
+                let empty_label = gtk::Label::new(Some(
+                    "No matches were found. Try a higher --tolerance, or update the cache if --no-update-cache was given",
+                ));
+                empty_label.set_halign(gtk::Align::Start);
+                b.add(&empty_label);
+            }
+        }
 
         b
     }
 
     pub fn increment_thunk_entry(&mut self) {
-        self.current_thunk.increment();
+        if let Some(current_thunk) = &mut self.current_thunk {
+            current_thunk.increment();
+        }
     }
 
     pub fn decrement_thunk_entry(&mut self) {
-        self.current_thunk.decrement();
+        if let Some(current_thunk) = &mut self.current_thunk {
+            current_thunk.decrement();
+        }
     }
 
     pub fn set_single_mode(&mut self, val: bool) {
         self.single_mode = val;
-        self.current_thunk.set_single_mode(self.single_mode)
+        if let Some(current_thunk) = &mut self.current_thunk {
+            current_thunk.set_single_mode(self.single_mode)
+        }
     }
 
     pub fn get_single_mode(&self) -> bool {
@@ -426,17 +461,17 @@ impl GuiState {
 
     pub fn zoom_in(&mut self) {
         self.zoom = self.zoom.zoom_in();
-        self.current_thunk.set_zoom(self.zoom)
+        self.set_current_zoom()
     }
 
     pub fn zoom_out(&mut self) {
         self.zoom = self.zoom.zoom_out();
-        self.current_thunk.set_zoom(self.zoom)
+        self.set_current_zoom()
     }
 
     pub fn set_native(&mut self, val: bool) {
         self.zoom = self.zoom.set_native(val);
-        self.current_thunk.set_zoom(self.zoom)
+        self.set_current_zoom()
     }
 
     pub fn get_native(&self) -> bool {
@@ -450,7 +485,7 @@ impl GuiState {
             self.thumb_choice = ThumbChoice::Video;
         }
 
-        self.current_thunk.set_choice(self.thumb_choice);
+        self.set_current_choice();
     }
 
     pub fn set_view_temporal(&mut self, val: bool) {
@@ -459,7 +494,7 @@ impl GuiState {
         } else {
             self.thumb_choice = ThumbChoice::Video;
         }
-        self.current_thunk.set_choice(self.thumb_choice);
+        self.set_current_choice();
     }
 
     pub fn set_view_rebuilt(&mut self, val: bool) {
@@ -468,7 +503,7 @@ impl GuiState {
         } else {
             self.thumb_choice = ThumbChoice::Video;
         }
-        self.current_thunk.set_choice(self.thumb_choice);
+        self.set_current_choice();
     }
 
     pub fn set_cropdetect(&mut self, val: bool) {
@@ -477,10 +512,28 @@ impl GuiState {
         } else {
             self.thumb_choice = ThumbChoice::Video;
         }
-        self.current_thunk.set_choice(self.thumb_choice);
+        self.set_current_choice();
+    }
+
+    fn set_current_zoom(&mut self) {
+        if let Some(current_thunk) = &mut self.current_thunk {
+            current_thunk.set_zoom(self.zoom)
+        }
+    }
+
+    fn set_current_choice(&mut self) {
+        if let Some(current_thunk) = &mut self.current_thunk {
+            current_thunk.set_choice(self.thumb_choice)
+        }
     }
 
     pub fn press_key(&mut self, key: &str) {
+        //With no matches there is nothing for any key to act on.
+        let current_thunk = match &mut self.current_thunk {
+            Some(current_thunk) => current_thunk,
+            None => return,
+        };
+
         match key {
             "a" => {
                 self.keypress_string.push('a');
@@ -502,11 +555,19 @@ impl GuiState {
             }
 
             "b" => {
-                self.current_thunk.vlc_all_slave();
+                current_thunk.play_all_simultaneously();
+            }
+
+            //keep the best video (by resolution, then bitrate, then filesize), trash the rest and move on.
+            "l" => {
+                current_thunk.resolve_keep_best();
+                self.next_thunk();
+                self.keypress_state = KeypressState::None;
+                self.keypress_string.clear();
             }
 
             "m" => {
-                self.current_thunk.vlc_all_seq();
+                current_thunk.play_all_seq();
             }
 
             "n" => {
@@ -549,9 +610,9 @@ impl GuiState {
                 if let Ok(idx) = self.keypress_string.parse::<usize>() {
                     match self.keypress_state {
                         KeypressState::None => {}
-                        KeypressState::Exclude => self.current_thunk.exclude(idx),
-                        KeypressState::Include => self.current_thunk.include(idx),
-                        KeypressState::View => self.current_thunk.vlc_video(idx),
+                        KeypressState::Exclude => current_thunk.exclude(idx),
+                        KeypressState::Include => current_thunk.include(idx),
+                        KeypressState::View => current_thunk.play_video(idx),
                         KeypressState::JumpTo => {
                             if idx < self.thunks.len() {
                                 self.thunk_idx = idx;
@@ -559,23 +620,23 @@ impl GuiState {
                             }
                         }
                         KeypressState::Resolve => {
-                            self.current_thunk.resolve(&self.keypress_string);
+                            current_thunk.resolve(&self.keypress_string);
                             self.next_thunk()
                         }
-                        KeypressState::Nautilus => self.current_thunk.nautilus_file(idx),
+                        KeypressState::Nautilus => current_thunk.nautilus_file(idx),
                     }
                 } else {
                     match self.keypress_state {
                         KeypressState::None => {}
                         KeypressState::Exclude => {}
                         KeypressState::Include => {}
-                        KeypressState::View => self.current_thunk.vlc_current_video(),
+                        KeypressState::View => current_thunk.play_current_video(),
                         KeypressState::JumpTo => {}
                         KeypressState::Resolve => {
-                            self.current_thunk.resolve(&self.keypress_string);
+                            current_thunk.resolve(&self.keypress_string);
                             self.next_thunk()
                         }
-                        KeypressState::Nautilus => self.current_thunk.nautilus_current_file(),
+                        KeypressState::Nautilus => current_thunk.nautilus_current_file(),
                     }
                 }
 
@@ -597,16 +658,23 @@ impl GuiState {
     }
 
     pub fn current_distance(&self) -> String {
-        self.current_thunk.distance()
+        match &self.current_thunk {
+            Some(current_thunk) => current_thunk.distance(),
+            None => "No matches".to_string(),
+        }
     }
 
     fn gen_thunk(&mut self) {
         //trace!("Moving to thunk {}", self.thunk_idx);
-        self.current_thunk = GuiEntryState::new(
-            self.thunks.get(self.thunk_idx).unwrap().clone(),
-            self.single_mode,
-            self.thumb_choice,
-            self.zoom,
-        );
+        self.current_thunk = self.thunks.get(self.thunk_idx).map(|thunk| {
+            GuiEntryState::new(
+                thunk.clone(),
+                self.single_mode,
+                self.thumb_choice,
+                self.zoom,
+                self.external_apps.clone(),
+                &self.row_cache,
+            )
+        });
     }
 }
@@ -1,6 +1,7 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
 use ffmpeg_cmdline_utils::*;
@@ -21,11 +22,55 @@ pub enum ThumbChoice {
     Rebuilt,
 }
 
+//Cropping a frame to less than 1/MIN_CROP_FRACTION of its width or height is treated as a misfire.
+const MIN_CROP_FRACTION: u32 = 10;
+
 #[derive(Debug)]
 struct ThumbRow {
     thumbs: Vec<RgbImage>,
 }
 
+//Reading frames with ffmpeg and detecting letterboxing are slow, and the thumbnails of a file are rebuilt
+//whenever the user returns to its group (or it appears in another group). So the GUI keeps the frames and
+//cropped frames of each file for the rest of the session, and they are only resized for each zoom.
+#[derive(Debug, Default, Clone)]
+pub struct ThumbRowCache {
+    video: Arc<Mutex<HashMap<PathBuf, Arc<ThumbRow>>>>,
+    cropdetect: Arc<Mutex<HashMap<PathBuf, Arc<ThumbRow>>>>,
+}
+
+impl ThumbRowCache {
+    fn video_row(&self, src_path: &Path) -> Arc<ThumbRow> {
+        Self::cached_row(&self.video, src_path, || {
+            ThumbRow::video_from_filename(src_path)
+        })
+    }
+
+    fn cropdetect_row(&self, src_path: &Path) -> Arc<ThumbRow> {
+        Self::cached_row(&self.cropdetect, src_path, || {
+            self.video_row(src_path).without_letterbox(src_path)
+        })
+    }
+
+    //Fetch the row for a file, creating it if this is the first time it is needed. The map is not locked
+    //while the row is created, so that the rows of a group can be created in parallel.
+    fn cached_row(
+        rows: &Mutex<HashMap<PathBuf, Arc<ThumbRow>>>,
+        src_path: &Path,
+        create: impl FnOnce() -> ThumbRow,
+    ) -> Arc<ThumbRow> {
+        if let Some(row) = rows.lock().unwrap().get(src_path) {
+            return Arc::clone(row);
+        }
+
+        let row = Arc::new(create());
+        rows.lock()
+            .unwrap()
+            .insert(src_path.to_path_buf(), Arc::clone(&row));
+        row
+    }
+}
+
 impl ThumbRow {
     pub fn video_from_filename(src_path: &Path) -> Self {
         let thumbs_10sec =
@@ -89,6 +134,11 @@ impl ThumbRow {
         }
 
         //otherwise, give up and return the fallback images (black square)
+        Self::fallback()
+    }
+
+    //Used for thumbnails that can't be generated, e.g. hash thumbnails of a file with no cached hash.
+    pub fn fallback() -> Self {
         Self {
             thumbs: Self::fallback_images(),
         }
@@ -133,22 +183,43 @@ impl ThumbRow {
         vec![RgbImage::new(100, 100), RgbImage::new(100, 100)]
     }
 
-    fn without_letterbox(&self) -> ThumbRow {
-        Self {
-            thumbs: VideoFrames::from_images(&self.thumbs)
-                .without_letterbox()
-                .into_inner(),
+    //Crop detection can misfire, e.g. on dark scenes, and crop almost all of each frame away. If that
+    //happens then report it and show the uncropped frames instead.
+    fn without_letterbox(&self, src_path: &Path) -> ThumbRow {
+        let cropped = VideoFrames::from_images(&self.thumbs)
+            .without_letterbox()
+            .into_inner();
+
+        let too_small = |cropped: &RgbImage, original: &RgbImage| {
+            cropped.width() * MIN_CROP_FRACTION < original.width()
+                || cropped.height() * MIN_CROP_FRACTION < original.height()
+        };
+        let misfired = cropped
+            .iter()
+            .zip(self.thumbs.iter())
+            .any(|(cropped, original)| too_small(cropped, original));
+        if misfired {
+            warn!(
+                "Letterbox detection cropped almost all of {}, so it is shown uncropped",
+                src_path.display()
+            );
+            return Self {
+                thumbs: self.thumbs.clone(),
+            };
         }
+
+        Self { thumbs: cropped }
     }
 }
 
 #[derive(Debug)]
 struct GuiThumbnail {
     filename: PathBuf,
-    hash: VideoHash,
+    hash: Option<VideoHash>,
 
-    base_video: Option<ThumbRow>,
-    base_cropdetect: Option<ThumbRow>,
+    row_cache: ThumbRowCache,
+    base_video: Option<Arc<ThumbRow>>,
+    base_cropdetect: Option<Arc<ThumbRow>>,
     spatial: Option<ThumbRow>,
     temporal: Option<ThumbRow>,
     rebuilt: Option<ThumbRow>,
@@ -163,12 +234,20 @@ struct GuiThumbnail {
 }
 
 impl GuiThumbnail {
-    pub fn new(filename: &Path, hash: VideoHash, zoom: ZoomState, choice: ThumbChoice) -> Self {
+    pub fn new(
+        filename: &Path,
+        hash: Option<VideoHash>,
+        zoom: ZoomState,
+        choice: ThumbChoice,
+        row_cache: ThumbRowCache,
+    ) -> Self {
         Self {
             filename: filename.to_path_buf(),
 
             hash,
 
+            row_cache,
+
             base_video: None,
             base_cropdetect: None,
             spatial: None,
@@ -200,32 +279,39 @@ impl GuiThumbnail {
         match self.choice {
             ThumbChoice::Video => {
                 if self.base_video.is_none() {
-                    self.base_video = Some(ThumbRow::video_from_filename(&self.filename))
+                    self.base_video = Some(self.row_cache.video_row(&self.filename))
                 }
             }
             ThumbChoice::CropdetectVideo => {
-                if self.base_video.is_none() {
-                    self.base_video = Some(ThumbRow::video_from_filename(&self.filename))
-                }
-
                 if self.base_cropdetect.is_none() {
-                    self.base_cropdetect =
-                        Some(self.base_video.as_ref().unwrap().without_letterbox())
+                    self.base_cropdetect = Some(self.row_cache.cropdetect_row(&self.filename))
                 }
             }
             ThumbChoice::Spatial => {
                 if self.spatial.is_none() {
-                    self.spatial = Some(ThumbRow::spatial_from_hash(&self.hash))
+                    self.spatial = Some(
+                        self.hash
+                            .as_ref()
+                            .map_or_else(ThumbRow::fallback, ThumbRow::spatial_from_hash),
+                    )
                 }
             }
             ThumbChoice::Temporal => {
                 if self.temporal.is_none() {
-                    self.temporal = Some(ThumbRow::temporal_from_hash(&self.hash))
+                    self.temporal = Some(
+                        self.hash
+                            .as_ref()
+                            .map_or_else(ThumbRow::fallback, ThumbRow::temporal_from_hash),
+                    )
                 }
             }
             ThumbChoice::Rebuilt => {
-                if self.spatial.is_none() {
-                    self.rebuilt = Some(ThumbRow::rebuilt_from_hash(&self.hash))
+                if self.rebuilt.is_none() {
+                    self.rebuilt = Some(
+                        self.hash
+                            .as_ref()
+                            .map_or_else(ThumbRow::fallback, ThumbRow::rebuilt_from_hash),
+                    )
                 }
             }
         }
@@ -279,13 +365,18 @@ pub struct GuiThumbnailSet {
 }
 
 impl GuiThumbnailSet {
-    pub fn new(info: Vec<(&Path, VideoHash)>, zoom: ZoomState, choice: ThumbChoice) -> Self {
+    pub fn new(
+        info: Vec<(&Path, Option<VideoHash>)>,
+        zoom: ZoomState,
+        choice: ThumbChoice,
+        row_cache: &ThumbRowCache,
+    ) -> Self {
         let mut thumbs = HashMap::new();
         info.into_par_iter()
             .map(|(src_path, hash)| {
                 (
                     src_path.to_path_buf(),
-                    GuiThumbnail::new(src_path, hash, zoom, choice),
+                    GuiThumbnail::new(src_path, hash, zoom, choice, row_cache.clone()),
                 )
             })
             .collect::<Vec<_>>()
@@ -1,3 +1,4 @@
+mod external_apps;
 mod gui_state;
 mod gui_thumbnail_set;
 mod gui_zoom;
@@ -5,4 +6,5 @@ mod img_ops;
 mod run_gui;
 
 //exports
+pub use external_apps::ExternalApps;
 pub use run_gui::run_gui;
@@ -12,15 +12,23 @@ use gtk::{
 use super::gui_state::GuiState;
 use crate::app::*;
 
-pub fn run_gui(thunks: Vec<ResolutionThunk>) -> Result<(), AppError> {
+pub fn run_gui(
+    thunks: Vec<ResolutionThunk>,
+    thumb_size: Option<u32>,
+    external_apps: ExternalApps,
+) -> Result<(), AppError> {
     if thunks.is_empty() {
-        info!("No matches were found. The GUI will not start");
-        return Ok(());
+        info!("No matches were found. The GUI will start with nothing to review");
     }
 
     gtk::init().map_err(|_e| AppError::GuiStartError)?;
 
-    let state: Rc<RefCell<GuiState>> = Rc::new(RefCell::new(GuiState::new(thunks, false)));
+    let state: Rc<RefCell<GuiState>> = Rc::new(RefCell::new(GuiState::new(
+        thunks,
+        false,
+        thumb_size,
+        external_apps,
+    )));
 
     let application = Application::new(Some("org.gtkrsnotes.demo"), Default::default())
         .map_err(|_e| AppError::GuiStartError)?;
@@ -43,12 +51,16 @@ fn rerender_gui(
         entries_box.remove(&child);
     }
 
-    idx_label.set_text(&format!(
-        "duplicate {} / {}. {}",
-        state.current_idx() + 1,
-        state.idx_len(),
-        state.current_distance()
-    ));
+    if state.is_empty() {
+        idx_label.set_text(&state.current_distance());
+    } else {
+        idx_label.set_text(&format!(
+            "duplicate {} / {}. {}",
+            state.current_idx() + 1,
+            state.idx_len(),
+            state.current_distance()
+        ));
+    }
 
     let new_interior = state.render();
     entries_box.add(&new_interior);
@@ -226,6 +238,10 @@ fn application_connect_activate_callback(app: &Application, state: &Rc<RefCell<G
 
     nav_box.add(&idx_label);
 
+    //With no matches the nav bar is still shown, so that it is clear the GUI has started, but none of it does
+    //anything.
+    nav_box.set_sensitive(!state.borrow().is_empty());
+
     nav_and_entries.add(&nav_box);
     nav_and_entries.add(&entries_box);
 
@@ -263,6 +279,10 @@ fn application_connect_activate_callback(app: &Application, state: &Rc<RefCell<G
 
     window.show_all();
 
+    if state.borrow().is_empty() {
+        rerender_gui(state, &entries_box, &window, &idx_label);
+    }
+
     //worker_thread.join().unwrap();
 }
 
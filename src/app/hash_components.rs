@@ -0,0 +1,180 @@
+use std::path::Path;
+
+use image::RgbImage;
+use itertools::Itertools;
+use vid_dup_finder_lib::*;
+use video_hash_filesystem_cache::*;
+
+use crate::app::*;
+
+const NUM_BUCKETS: usize = 10;
+
+//The rate at which the library samples frames when creating a hash. The library does not expose this, so it
+//must be kept in step with the library version by hand. It is only used to give approximate timestamps.
+const HASH_SAMPLE_FPS: f64 = 1.0 / 3.0;
+
+//The library only reports the combined distance between two hashes. The spatial and temporal parts of a hash
+//can be rendered back into thumbnails though, so the difference between those thumbnails is used as an estimate
+//of how much each part contributed to a match.
+fn thumb_difference(a: &[RgbImage], b: &[RgbImage]) -> Option<f64> {
+    if a.len() != b.len() || a.is_empty() {
+        return None;
+    }
+
+    let mut total_diff = 0u64;
+    let mut num_subpixels = 0u64;
+    for (img_a, img_b) in a.iter().zip(b.iter()) {
+        if img_a.dimensions() != img_b.dimensions() {
+            return None;
+        }
+        for (subpx_a, subpx_b) in img_a.as_raw().iter().zip(img_b.as_raw().iter()) {
+            total_diff += (*subpx_a as i32 - *subpx_b as i32).unsigned_abs() as u64;
+            num_subpixels += 1;
+        }
+    }
+
+    if num_subpixels == 0 {
+        return None;
+    }
+
+    Some(total_diff as f64 / (num_subpixels as f64 * 255.0))
+}
+
+fn fetch_hash(cache: &VideoHashFilesystemCache, path: &Path) -> Result<VideoHash, AppError> {
+    cache
+        .fetch(path)
+        .map_err(|e| AppError::NoHashError(path.to_path_buf(), e))
+}
+
+//Print the distance between two files, as would be used in a search, along with estimates of the spatial
+//and temporal parts of the distance.
+pub fn print_comparison(
+    path_a: &Path,
+    path_b: &Path,
+    cache: &VideoHashFilesystemCache,
+) -> Result<(), AppError> {
+    let hash_a = fetch_hash(cache, path_a)?;
+    let hash_b = fetch_hash(cache, path_b)?;
+
+    let distance = hash_a.normalized_levenshtein_distance(&hash_b).value();
+    let spatial = thumb_difference(&hash_a.spatial_thumbs(), &hash_b.spatial_thumbs());
+    let temporal = thumb_difference(&hash_a.temporal_thumbs(), &hash_b.temporal_thumbs());
+
+    println!("A: {}", path_a.display());
+    println!("B: {}", path_b.display());
+    println!("Distance:            {:.3}", distance);
+    println!("Similarity:          {:.3}", 1.0 - distance);
+    println!("Spatial difference:  {}", render_difference(spatial));
+    println!("Temporal difference: {}", render_difference(temporal));
+
+    Ok(())
+}
+
+//The estimated difference of a single frame of two hashes, if both hashes contain that frame.
+fn frame_difference(a: &[RgbImage], b: &[RgbImage], idx: usize) -> Option<f64> {
+    match (a.get(idx..=idx), b.get(idx..=idx)) {
+        (Some(frame_a), Some(frame_b)) => thumb_difference(frame_a, frame_b),
+        _ => None,
+    }
+}
+
+fn render_difference(difference: Option<f64>) -> String {
+    difference.map_or_else(
+        || "-".to_string(),
+        |difference| format!("{:.3}", difference),
+    )
+}
+
+//Print the estimated spatial and temporal difference of each frame sampled from the two given files,
+//so that the moments which drove (or spoiled) a match can be found and checked in a video player.
+pub fn print_match_explanation(
+    path_a: &Path,
+    path_b: &Path,
+    cache: &VideoHashFilesystemCache,
+) -> Result<(), AppError> {
+    let hash_a = fetch_hash(cache, path_a)?;
+    let hash_b = fetch_hash(cache, path_b)?;
+
+    let (spatial_a, temporal_a) = (hash_a.spatial_thumbs(), hash_a.temporal_thumbs());
+    let (spatial_b, temporal_b) = (hash_b.spatial_thumbs(), hash_b.temporal_thumbs());
+
+    println!("A: {}", path_a.display());
+    println!("B: {}", path_b.display());
+    println!(
+        "Distance: {:.3}",
+        hash_a.normalized_levenshtein_distance(&hash_b).value()
+    );
+    println!(
+        "{:>5} {:>10} {:>8} {:>8}",
+        "frame", "timestamp", "spatial", "temporal"
+    );
+
+    let num_frames = spatial_a
+        .len()
+        .max(spatial_b.len())
+        .max(temporal_a.len())
+        .max(temporal_b.len());
+    for idx in 0..num_frames {
+        let timestamp = idx as f64 / HASH_SAMPLE_FPS;
+        println!(
+            "{:>5} {:>9.1}s {:>8} {:>8}",
+            idx,
+            timestamp,
+            render_difference(frame_difference(&spatial_a, &spatial_b, idx)),
+            render_difference(frame_difference(&temporal_a, &temporal_b, idx)),
+        );
+    }
+
+    Ok(())
+}
+
+fn bucket(difference: f64) -> usize {
+    ((difference * NUM_BUCKETS as f64) as usize).min(NUM_BUCKETS - 1)
+}
+
+//Log a histogram of the estimated spatial and temporal differences between every pair of matched videos.
+pub fn log_component_histogram(search_output: &SearchOutput, cache: &VideoHashFilesystemCache) {
+    let mut spatial_counts = [0usize; NUM_BUCKETS];
+    let mut temporal_counts = [0usize; NUM_BUCKETS];
+    let mut num_pairs = 0;
+
+    for group in search_output.dup_groups() {
+        let thumbs = search_output::group_paths(group)
+            .filter_map(|path| cache.fetch(path).ok())
+            .map(|hash| (hash.spatial_thumbs(), hash.temporal_thumbs()))
+            .collect::<Vec<_>>();
+
+        for pair in thumbs.iter().combinations(2) {
+            let (spatial_a, temporal_a) = pair[0];
+            let (spatial_b, temporal_b) = pair[1];
+
+            if let (Some(spatial), Some(temporal)) = (
+                thumb_difference(spatial_a, spatial_b),
+                thumb_difference(temporal_a, temporal_b),
+            ) {
+                spatial_counts[bucket(spatial)] += 1;
+                temporal_counts[bucket(temporal)] += 1;
+                num_pairs += 1;
+            }
+        }
+    }
+
+    if num_pairs == 0 {
+        return;
+    }
+
+    info!(
+        "Estimated spatial and temporal differences between {} matched pairs:",
+        num_pairs
+    );
+    info!("{:>11} {:>8} {:>8}", "difference", "spatial", "temporal");
+    for i in 0..NUM_BUCKETS {
+        info!(
+            "{:>4.1} - {:>3.1} {:>8} {:>8}",
+            i as f64 / NUM_BUCKETS as f64,
+            (i + 1) as f64 / NUM_BUCKETS as f64,
+            spatial_counts[i],
+            temporal_counts[i]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use serde_json::json;
+use video_hash_filesystem_cache::*;
+
+use super::{app_fns::stats_json, AppError};
+
+//Print everything the cache holds for a single file, as JSON. The hash is given as the hex of its
+//serialized bytes, so that two entries can be compared by eye.
+pub fn print_inspection(path: &Path, cache: &VideoHashFilesystemCache) -> Result<(), AppError> {
+    if !cache.all_cached_paths().iter().any(|p| p == path) {
+        println!("{} is not in the cache", path.display());
+        return Ok(());
+    }
+
+    let hash = match cache.fetch(path) {
+        Ok(hash) => match bincode::serialize(&hash) {
+            Ok(bytes) => json!({
+                "hex": bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>()
+            }),
+            Err(e) => json!({ "error": e.to_string() }),
+        },
+        Err(e) => json!({ "error": e.to_string() }),
+    };
+
+    let stats = match cache.fetch_stats(path) {
+        Ok(stats) => stats_json(&stats),
+        Err(e) => json!({ "error": e.to_string() }),
+    };
+
+    let inspection = json!({
+        "path": path,
+        "hash": hash,
+        "stats": stats,
+    });
+
+    let mut out = BufWriter::new(std::io::stdout());
+    serde_json::to_writer_pretty(&mut out, &inspection)
+        .map_err(|e| AppError::OutputError(e.into()))?;
+    writeln!(out).map_err(AppError::OutputError)?;
+    Ok(())
+}
+
+//Write every entry in the cache to a file as JSON, sorted by path. The status of each entry is "Video" if it
+//has a hash, or otherwise the error that occurred when it was hashed (e.g. if it was too short).
+//
+//With dry_run nothing is written, and the number of entries that would have been written is printed instead.
+pub fn dump_cache_json(
+    cache: &VideoHashFilesystemCache,
+    output_path: &Path,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let mut cached_paths = cache.all_cached_paths();
+    cached_paths.sort();
+
+    let entries = cached_paths
+        .iter()
+        .map(|path| {
+            let status = match cache.fetch(path) {
+                Ok(_hash) => "Video".to_string(),
+                Err(e) => e.to_string(),
+            };
+            let stats = match cache.fetch_stats(path) {
+                Ok(stats) => stats_json(&stats),
+                Err(e) => json!({ "error": e.to_string() }),
+            };
+            json!({
+                "path": path,
+                "status": status,
+                "stats": stats,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if dry_run {
+        println!(
+            "Would write {} cache entries to {}",
+            entries.len(),
+            output_path.display()
+        );
+        return Ok(());
+    }
+
+    let write_err = |e| AppError::DumpCacheJsonError(output_path.to_path_buf(), e);
+    let file = File::create(output_path).map_err(write_err)?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &entries)
+        .map_err(|e| write_err(e.into()))?;
+
+    info!(
+        "Wrote {} cache entries to {}",
+        entries.len(),
+        output_path.display()
+    );
+    Ok(())
+}
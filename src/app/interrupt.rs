@@ -0,0 +1,201 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use video_hash_filesystem_cache::*;
+
+use crate::app::*;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+//How long to wait after Ctrl-C for the ffmpeg processes that it killed to be recorded in the cache.
+const KILLED_FFMPEG_GRACE: Duration = Duration::from_secs(1);
+
+//Run a cache update, saving the cache and exiting if the user presses Ctrl-C. Otherwise every file hashed
+//since the cache was last saved would be hashed again on the next run.
+//
+//The signal handler only sets a flag. The cache is saved from a separate thread which watches the flag, so
+//the save takes the cache's locks in the same way as the saves made during the update (and the rayon
+//workers carry on until the process exits).
+//
+//Ffmpeg processes started by the update also receive Ctrl-C, so the files being hashed at that moment are
+//cached as errors, which the cache never retries. Those files can't be told apart from files which failed for
+//other reasons, so every file which failed during this update is recorded beside the cache, and is removed from
+//the cache by the next update (see forget_interrupted_files) so that it is hashed again.
+pub fn save_cache_on_interrupt<T>(
+    cache: &VideoHashFilesystemCache,
+    cache_path: &Path,
+    update: impl FnOnce() -> T,
+) -> T {
+    if !set_sigint_handler(true) {
+        return update();
+    }
+
+    let previously_cached = cache.all_cached_paths().into_iter().collect::<HashSet<_>>();
+
+    struct SetOnDrop<'a>(&'a AtomicBool);
+    impl Drop for SetOnDrop<'_> {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let finished = AtomicBool::new(false);
+    let ret = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while !finished.load(Ordering::SeqCst) {
+                if INTERRUPTED.load(Ordering::SeqCst) {
+                    warn!("Interrupted. Saving the cache before exiting");
+                    std::thread::sleep(KILLED_FFMPEG_GRACE);
+                    if let Err(e) = cache.save() {
+                        error!("Failed to save the cache: {}", e);
+                    }
+                    record_interrupted_files(cache, cache_path, &previously_cached);
+                    std::process::exit(130);
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        });
+
+        let _finished = SetOnDrop(&finished);
+        update()
+    });
+
+    set_sigint_handler(false);
+    ret
+}
+
+#[cfg(target_family = "unix")]
+extern "C" fn on_sigint(_signum: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+//Install the Ctrl-C handler, or restore the default behaviour of exiting immediately. Returns false if the
+//handler could not be installed.
+#[cfg(target_family = "unix")]
+fn set_sigint_handler(install: bool) -> bool {
+    let handler = if install {
+        on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t
+    } else {
+        libc::SIG_DFL
+    };
+
+    let ret = unsafe { libc::signal(libc::SIGINT, handler) };
+    if ret == libc::SIG_ERR {
+        warn!(
+            "Failed to set Ctrl-C handler: {}",
+            std::io::Error::last_os_error()
+        );
+        return false;
+    }
+    true
+}
+
+#[cfg(not(target_family = "unix"))]
+fn set_sigint_handler(_install: bool) -> bool {
+    false
+}
+
+//The files which failed to hash during an interrupted update are stored in a small file beside the cache.
+fn interrupted_file_path(cache_path: &Path) -> PathBuf {
+    let mut file_name = cache_path
+        .file_name()
+        .map(OsString::from)
+        .unwrap_or_default();
+    file_name.push(".interrupted");
+    cache_path.with_file_name(file_name)
+}
+
+fn record_interrupted_files(
+    cache: &VideoHashFilesystemCache,
+    cache_path: &Path,
+    previously_cached: &HashSet<PathBuf>,
+) {
+    let mut failed_paths = cache
+        .all_cached_paths()
+        .into_iter()
+        .filter(|src_path| !previously_cached.contains(src_path) && cache.fetch(src_path).is_err())
+        .collect::<Vec<_>>();
+    if failed_paths.is_empty() {
+        return;
+    }
+    failed_paths.sort();
+
+    warn!(
+        "{} files could not be hashed before the interruption, possibly because their ffmpeg was also interrupted. They will be hashed again by the next run which updates the cache:",
+        failed_paths.len()
+    );
+    for src_path in &failed_paths {
+        warn!("    {}", src_path.display());
+    }
+
+    let interrupted_path = interrupted_file_path(cache_path);
+    let result = serde_json::to_vec(&failed_paths)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| fs::write(&interrupted_path, contents).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        warn!(
+            "Failed to record these files at {}: {}. To hash them again, delete the cache at {}",
+            interrupted_path.display(),
+            e,
+            cache_path.display()
+        );
+    }
+}
+
+//Remove the files recorded by an interrupted update from the cache, so that this update hashes them again.
+pub fn forget_interrupted_files(
+    cache: &VideoHashFilesystemCache,
+    cache_path: &Path,
+) -> Result<(), AppError> {
+    let interrupted_path = interrupted_file_path(cache_path);
+    let contents = match fs::read(&interrupted_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            warn!(
+                "Failed to read files from an interrupted update at {}: {}",
+                interrupted_path.display(),
+                e
+            );
+            return Ok(());
+        }
+    };
+
+    match serde_json::from_slice::<HashSet<PathBuf>>(&contents) {
+        Ok(interrupted) => {
+            let (removed, kept): (Vec<PathBuf>, Vec<PathBuf>) = cache
+                .all_cached_paths()
+                .into_iter()
+                .partition(|src_path| interrupted.contains(src_path));
+            if !removed.is_empty() {
+                info!(
+                    "Hashing {} files again, as they failed during an interrupted update",
+                    removed.len()
+                );
+                prune_cache::remove_cached_paths(cache, &kept, &removed)?;
+                cache.save()?;
+            }
+        }
+        Err(e) => warn!(
+            "Failed to parse files from an interrupted update at {}: {}",
+            interrupted_path.display(),
+            e
+        ),
+    }
+
+    if let Err(e) = fs::remove_file(&interrupted_path) {
+        warn!(
+            "Failed to remove files from an interrupted update at {}: {}",
+            interrupted_path.display(),
+            e
+        );
+    }
+    Ok(())
+}
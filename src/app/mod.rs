@@ -1,19 +1,44 @@
 mod app_cfg;
 mod app_fns;
 mod arg_parse;
+mod audio_hash;
+mod auto_resolve;
+mod cache_lock;
+mod cache_plan;
+mod content_hash;
+mod dir_summary;
 mod errors;
+mod exact_dups;
+mod export;
+mod ffmpeg_version;
+mod file_ops;
 #[cfg(all(target_family = "unix", feature = "gui"))]
 mod gui;
-#[cfg(all(target_family = "unix", feature = "gui"))]
+mod hash_components;
+mod inspect;
+mod interrupt;
+mod path_filter;
+mod prune_cache;
+mod resolution_plan;
 mod resolution_thunk;
+mod results_file;
+mod resume;
+mod robustness_check;
+mod run_stats;
 mod search_output;
+mod subtitles;
+mod tolerance_sweep;
+mod tui;
+mod tune_tolerance;
 
 pub(crate) use app_cfg::*;
 pub(crate) use errors::*;
 #[cfg(all(target_family = "unix", feature = "gui"))]
-pub(crate) use gui::run_gui;
-#[cfg(all(target_family = "unix", feature = "gui"))]
+pub(crate) use gui::{run_gui, ExternalApps};
+pub(crate) use path_filter::PathFilter;
+pub(crate) use resolution_plan::{KeeperPolicy, KeeperRules};
 pub(crate) use resolution_thunk::*;
-pub(crate) use search_output::SearchOutput;
+pub(crate) use run_stats::RunStats;
+pub(crate) use search_output::{DupGroup, GroupKind, SearchOutput, SearchReport};
 
 pub use app_fns::run_app;
@@ -0,0 +1,154 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use video_hash_filesystem_cache::*;
+
+use crate::app::*;
+
+//Filters applied to the files found under --files and --with-refs, on top of the exclusions understood by
+//FileProjection. Files are filtered before they are hashed, so ffmpeg is never run on a filtered file.
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    cand_dirs: Vec<PathBuf>,
+    no_recurse: bool,
+    incl_exts: Option<Vec<String>>,
+    excl_regexes: Vec<regex::Regex>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    follow_symlinks: bool,
+}
+
+impl PathFilter {
+    pub fn from_cfg(dir_cfg: &DirCfg) -> Self {
+        Self {
+            cand_dirs: dir_cfg.cand_dirs.clone(),
+            no_recurse: dir_cfg.no_recurse,
+            incl_exts: dir_cfg.incl_exts.clone(),
+            excl_regexes: dir_cfg.excl_regexes.clone(),
+            min_size: dir_cfg.min_size,
+            max_size: dir_cfg.max_size,
+            follow_symlinks: dir_cfg.follow_symlinks,
+        }
+    }
+
+    fn keep_ref(&self, path: &Path) -> bool {
+        //Symlinked directories are never descended into when searching for files, but symlinks to files are
+        //found. Unless --follow-symlinks is given, skip them too.
+        if !self.follow_symlinks {
+            let is_symlink = std::fs::symlink_metadata(path)
+                .map(|metadata| metadata.file_type().is_symlink())
+                .unwrap_or(false);
+            if is_symlink {
+                return false;
+            }
+        }
+
+        //With --exclude-regex, files are matched using their whole path.
+        if !self.excl_regexes.is_empty() {
+            let path_str = path.to_string_lossy();
+            if self
+                .excl_regexes
+                .iter()
+                .any(|excl_regex| excl_regex.is_match(&path_str))
+            {
+                return false;
+            }
+        }
+
+        //With --min-size and --max-size, files whose size can't be read are kept, so that the error is reported
+        //when they are hashed.
+        if self.min_size.is_some() || self.max_size.is_some() {
+            if let Ok(metadata) = std::fs::metadata(path) {
+                let size = metadata.len();
+                let too_small = matches!(self.min_size, Some(min_size) if size < min_size);
+                let too_large = matches!(self.max_size, Some(max_size) if size > max_size);
+                if too_small || too_large {
+                    return false;
+                }
+            }
+        }
+
+        //With --include-exts, only files with one of the given extensions (ignoring case) are kept.
+        match &self.incl_exts {
+            Some(incl_exts) => match path.extension() {
+                Some(ext) => incl_exts.contains(&ext.to_string_lossy().to_lowercase()),
+                None => false,
+            },
+            None => true,
+        }
+    }
+
+    fn keep_cand(&self, path: &Path) -> bool {
+        if !self.keep_ref(path) {
+            return false;
+        }
+
+        //With --no-recurse, only files given directly, or directly inside a directory given by --files, are kept.
+        if self.no_recurse {
+            let is_direct_child = self
+                .cand_dirs
+                .iter()
+                .any(|cand_dir| path == cand_dir || path.parent() == Some(cand_dir.as_path()));
+            if !is_direct_child {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    //Remove all files that don't pass the filter from a projection of --files.
+    pub fn apply_to_cands(&self, projection: &mut FileProjection) {
+        self.apply(projection, |path| self.keep_cand(path));
+    }
+
+    //Remove all files that don't pass the filter from a projection of --with-refs.
+    pub fn apply_to_refs(&self, projection: &mut FileProjection) {
+        self.apply(projection, |path| self.keep_ref(path));
+    }
+
+    fn apply(&self, projection: &mut FileProjection, keep: impl Fn(&Path) -> bool) {
+        let kept = projection
+            .projected_files()
+            .iter()
+            .filter(|path| keep(path))
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        let kept = dedup_inodes(kept).into_iter().collect::<HashSet<PathBuf>>();
+        projection.project_using_list(&kept);
+    }
+}
+
+//If the same file is found more than once (through hardlinks, bind mounts or, with --follow-symlinks, symlinks)
+//then it would be hashed more than once and reported as a duplicate of itself. So keep only the first path (in
+//sorted order) to each file. Files whose metadata can't be read are always kept.
+#[cfg(target_family = "unix")]
+fn dedup_inodes(mut paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    paths.sort();
+    let mut seen_inodes = HashSet::new();
+    paths
+        .into_iter()
+        .filter(|path| match std::fs::metadata(path) {
+            Ok(metadata) => {
+                let is_new = seen_inodes.insert((metadata.dev(), metadata.ino()));
+                if !is_new {
+                    debug!(
+                        "Skipping {} because it is the same file as another path",
+                        path.display()
+                    );
+                }
+                is_new
+            }
+            Err(_) => true,
+        })
+        .collect()
+}
+
+#[cfg(not(target_family = "unix"))]
+fn dedup_inodes(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    paths
+}
@@ -0,0 +1,104 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use video_hash_filesystem_cache::*;
+
+use crate::app::*;
+
+//Remove every cache entry whose file no longer exists, wherever it is on disk.
+pub fn prune_cache(cache: &VideoHashFilesystemCache, dry_run: bool) -> Result<(), AppError> {
+    let cached_paths = cache.all_cached_paths();
+    let (existing, missing): (Vec<PathBuf>, Vec<PathBuf>) =
+        cached_paths.into_iter().partition(|path| path.exists());
+
+    if dry_run {
+        for path in &missing {
+            println!("Would remove {} from the cache", path.display());
+        }
+        return Ok(());
+    }
+
+    if missing.is_empty() {
+        info!("No cache entries to prune");
+        return Ok(());
+    }
+
+    remove_cached_paths(cache, &existing, &missing)?;
+    cache.save()?;
+
+    info!("Removed {} entries from the cache", missing.len());
+    Ok(())
+}
+
+//Remove every cache entry whose file no longer exists (and with discard_non_videos, every entry for a file that
+//could not be hashed), then rewrite the cache file.
+pub fn compact_cache(
+    cache: &VideoHashFilesystemCache,
+    cache_path: &Path,
+    discard_non_videos: bool,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let (kept, discarded): (Vec<PathBuf>, Vec<PathBuf>) = cache
+        .all_cached_paths()
+        .into_iter()
+        .partition(|path| path.exists() && !(discard_non_videos && cache.fetch(path).is_err()));
+
+    if dry_run {
+        for path in &discarded {
+            println!("Would remove {} from the cache", path.display());
+        }
+        return Ok(());
+    }
+
+    let size_before = cache_file_size(cache_path);
+    if !discarded.is_empty() {
+        remove_cached_paths(cache, &kept, &discarded)?;
+    }
+    cache.save()?;
+    let size_after = cache_file_size(cache_path);
+
+    info!(
+        "Removed {} entries from the cache. Cache file size: {} before, {} after",
+        discarded.len(),
+        size_before,
+        size_after
+    );
+    Ok(())
+}
+
+//The cache only removes entries for missing files when they are inside the projection it is updated
+//with. So the cache is updated with a projection rooted at the root of every cached path, containing
+//only the cached files that are to be kept. Nothing new is found, so nothing new is hashed.
+pub fn remove_cached_paths(
+    cache: &VideoHashFilesystemCache,
+    kept: &[PathBuf],
+    removed: &[PathBuf],
+) -> Result<(), AppError> {
+    let roots = removed
+        .iter()
+        .chain(kept.iter())
+        .filter_map(|path| path.ancestors().last())
+        .map(Path::to_path_buf)
+        .collect::<HashSet<PathBuf>>();
+    let kept = kept.iter().cloned().collect::<HashSet<PathBuf>>();
+
+    let mut projection = FileProjection::new(roots, &[] as &[PathBuf], &[] as &[&str])
+        .map_err(AppError::from_cand_exclusion_error)?;
+    projection.project_using_list(&kept);
+    for err in cache.update_using_fs(&projection)? {
+        warn!("{}", AppError::from(err).display_chain());
+    }
+
+    Ok(())
+}
+
+fn cache_file_size(cache_path: &Path) -> String {
+    match std::fs::metadata(cache_path) {
+        Ok(metadata) => byte_unit::Byte::from_bytes(metadata.len() as u128)
+            .get_appropriate_unit(false)
+            .to_string(),
+        Err(_) => "unknown".to_string(),
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use video_hash_filesystem_cache::*;
+
+use super::{
+    file_ops::{self, TrashError},
+    subtitles, DupGroup,
+};
+
+//How to choose which file in a group of duplicates should be kept.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeeperPolicy {
+    //Keep the largest file
+    Largest,
+
+    //Keep the file with the best resolution, then bitrate, then filesize.
+    BestQuality,
+}
+
+impl KeeperPolicy {
+    //The index of the file that should be kept, given the stats of each file in a group.
+    //On ties, the earliest file is kept.
+    pub fn keeper_idx<'a>(self, stats: impl IntoIterator<Item = &'a VideoStats>) -> Option<usize> {
+        stats
+            .into_iter()
+            .enumerate()
+            .max_by_key(|(i, stats)| {
+                let quality = match self {
+                    KeeperPolicy::Largest => (0, 0, stats.size()),
+                    KeeperPolicy::BestQuality => {
+                        let (x, y) = stats.resolution();
+                        (x * y, stats.bit_rate(), stats.size())
+                    }
+                };
+                (quality, std::cmp::Reverse(*i))
+            })
+            .map(|(i, _stats)| i)
+    }
+}
+
+//All of the rules used to choose which file in a group to keep.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeeperRules {
+    pub policy: KeeperPolicy,
+
+    //Files in these directories are always kept, and are preferred when choosing a keeper.
+    pub protect_dirs: Vec<PathBuf>,
+
+    //Files given by --keep-list. These are always kept, even when resolving a group by hand.
+    pub keep_list: HashSet<PathBuf>,
+
+    //Prefer files with subtitles over files without (after protection, but before the policy).
+    pub prefer_subtitles: bool,
+
+    //Do not trash anything unless the files to be kept can be read.
+    pub require_readable: bool,
+}
+
+//Whether a file is inside any of the directories given by --protect, or is in the --keep-list. Protected
+//files are never trashed.
+pub fn is_protected(path: &Path, rules: &KeeperRules) -> bool {
+    rules.protect_dirs.iter().any(|dir| path.starts_with(dir)) || rules.keep_list.contains(path)
+}
+
+//Narrow down the candidates to those for which the predicate is true, unless it is true for none of them.
+fn prefer(candidate_idxs: Vec<usize>, pred: impl Fn(usize) -> bool) -> Vec<usize> {
+    let preferred = candidate_idxs
+        .iter()
+        .copied()
+        .filter(|&i| pred(i))
+        .collect::<Vec<_>>();
+
+    if preferred.is_empty() {
+        candidate_idxs
+    } else {
+        preferred
+    }
+}
+
+//The index of the file that should be kept.
+pub fn choose_keeper(rules: &KeeperRules, paths: &[&Path], stats: &[VideoStats]) -> Option<usize> {
+    let mut candidate_idxs = (0..paths.len()).collect::<Vec<_>>();
+
+    candidate_idxs = prefer(candidate_idxs, |i| is_protected(paths[i], rules));
+    if rules.prefer_subtitles {
+        candidate_idxs = prefer(candidate_idxs, |i| subtitles::has_subtitles(paths[i]));
+    }
+
+    rules
+        .policy
+        .keeper_idx(candidate_idxs.iter().map(|&i| &stats[i]))
+        .map(|i| candidate_idxs[i])
+}
+
+//A single filesystem operation in the resolution of a group of duplicates. All paths are resolved
+//when the operation is planned, so a plan can be shown to the user before it is executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedOp {
+    Keep(PathBuf),
+    Trash { src: PathBuf, dest: PathBuf },
+    SystemTrash(PathBuf),
+    Move { src: PathBuf, dest: PathBuf },
+}
+
+//The files in a group which would be kept, and the files which would be trashed. If the group has a
+//reference then the reference is kept, otherwise the keeper is chosen by the given rules. Protected files
+//are always kept.
+pub fn split_group<'a>(
+    group: &'a DupGroup,
+    cache: &VideoHashFilesystemCache,
+    rules: &KeeperRules,
+) -> (Vec<&'a Path>, Vec<&'a Path>) {
+    let mut dup_paths = group.duplicates().collect::<Vec<_>>();
+
+    let keeper = match group.reference() {
+        Some(reference) => Some(reference),
+        None => {
+            let stats = dup_paths
+                .iter()
+                .map(|dup_path| cache.fetch_stats(dup_path).unwrap_or_default())
+                .collect::<Vec<_>>();
+            choose_keeper(rules, &dup_paths, &stats).map(|keeper_idx| dup_paths.remove(keeper_idx))
+        }
+    };
+
+    let (protected, to_trash): (Vec<&Path>, Vec<&Path>) = dup_paths
+        .into_iter()
+        .partition(|dup_path| is_protected(dup_path, rules));
+
+    let to_keep = keeper.into_iter().chain(protected).collect();
+    (to_keep, to_trash)
+}
+
+//The operation which trashes a file, into the trash directory if one was given or otherwise the system trash.
+pub fn trash_op(trash_dir: Option<&Path>, src: &Path) -> Result<PlannedOp, TrashError> {
+    Ok(match trash_dir {
+        Some(trash_dir) => PlannedOp::Trash {
+            src: src.to_path_buf(),
+            dest: file_ops::trash_path(trash_dir, src)?,
+        },
+        None => PlannedOp::SystemTrash(src.to_path_buf()),
+    })
+}
+
+//The operation which moves a file into dest_dir for review. The file keeps its path relative to the search
+//directory it was found in (the innermost one, if search directories are nested). Files outside every search
+//directory keep their whole absolute path, as they would in the trash directory.
+pub fn relocate_op(
+    dest_dir: &Path,
+    search_dirs: &[PathBuf],
+    src: &Path,
+) -> Result<PlannedOp, TrashError> {
+    let relative_path = match search_dirs
+        .iter()
+        .filter_map(|search_dir| src.strip_prefix(search_dir).ok())
+        .min_by_key(|relative_path| relative_path.components().count())
+    {
+        Some(relative_path) => relative_path,
+        None => src.strip_prefix("/")?,
+    };
+
+    Ok(PlannedOp::Move {
+        src: src.to_path_buf(),
+        dest: dest_dir.join(relative_path),
+    })
+}
+
+//Check that every file a plan keeps can be read, so that the only readable copy of a video is never trashed.
+pub fn check_keepers_readable(ops: &[PlannedOp]) -> Result<(), TrashError> {
+    for op in ops {
+        if let PlannedOp::Keep(path) = op {
+            file_ops::check_readable(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+//Perform the operations of a plan in order, stopping at the first failure. With --dry-run nothing is changed on
+//disk, and each operation is printed instead.
+pub fn execute(ops: &[PlannedOp], dry_run: bool) -> Result<(), TrashError> {
+    for op in ops {
+        match op {
+            PlannedOp::Keep(path) if dry_run => println!("Would keep {}", path.display()),
+            PlannedOp::Keep(_path) => (),
+            PlannedOp::Trash { src, dest } => file_ops::trash_file(src, dest, dry_run)?,
+            PlannedOp::SystemTrash(src) => file_ops::system_trash_file(src, dry_run)?,
+            PlannedOp::Move { src, dest } => file_ops::move_path(src, dest, dry_run)?,
+        }
+    }
+
+    Ok(())
+}
@@ -1,14 +1,18 @@
-use std::{
-    ffi::OsString,
-    path::{Path, PathBuf},
-};
+use std::path::{Path, PathBuf};
 
 use lazy_static::lazy_static;
 use thiserror::Error;
 use vid_dup_finder_lib::*;
 use video_hash_filesystem_cache::*;
 use ResolutionError::*;
-use TrashError::*;
+
+use super::{
+    app_cfg::{AppCfg, AutoResolveMetric, MatchImageLayout},
+    app_fns::{match_image_font, write_image},
+    file_ops::{get_new_name_if_path_already_exists, TrashError, TrashError::*},
+    resolution_plan::{self, KeeperPolicy, KeeperRules, PlannedOp},
+    subtitles, DupGroup,
+};
 
 fn with_extension(recipient: &Path, donor: &Path) -> PathBuf {
     match donor.extension() {
@@ -22,46 +26,6 @@ fn with_basename(recipient: &Path, donor: &Path) -> PathBuf {
     recipient.with_file_name(new_basename)
 }
 
-#[derive(Error, Debug)]
-
-pub enum TrashError {
-    #[error("Gui Trash Path not supplied in command line arguments")]
-    NoTrashPathError,
-
-    #[error("Failed to open file at path path {0}: {1}")]
-    FileOpenError(String, #[source] std::io::Error),
-
-    #[error("Failed to strip prefix '/' from path: {0}")]
-    StripPrefixError(#[from] std::path::StripPrefixError),
-
-    #[error("I/O Error at path {0}: {1}")]
-    IoError(String, #[source] std::io::Error),
-
-    #[error("Failed to delete file: {0}")]
-    DeleteFileFailure(String, #[source] std::io::Error),
-
-    #[error("Source file does not exist: {0}")]
-    SourceFileMissing(String),
-
-    #[error("Destination already exists: {0}")]
-    DestFileExists(String),
-
-    #[error("Failed to create parent directory for trash file: {0}")]
-    CreateParentDirFailure(String),
-
-    #[error("Coudn't extract parent directory from string: {0}")]
-    ExtractParentDirFailure(String),
-
-    #[error("move_path: Failed to copy file {0} to {1}")]
-    CopyFailError(String, String),
-
-    #[error("move_path: Unhandled error copying {0} to {1}")]
-    UnhandledError(String, String),
-
-    #[error("move_path: std::fs::rename returned None for moving {0} to {1}")]
-    RenameNoneError(String, String),
-}
-
 #[derive(Error, Debug)]
 pub enum ResolutionError {
     #[error("Failed to perform trash operation: {0}")]
@@ -92,6 +56,7 @@ struct ResolutionThunkEntry {
     hash: Option<VideoHash>,
     is_reference: bool,
     stats: VideoStats,
+    has_subtitles: bool,
 }
 
 #[derive(Debug)]
@@ -108,36 +73,79 @@ pub struct WinningStats {
     pub res: bool,
     pub bitrate: bool,
     pub has_audio: bool,
+    pub has_subtitles: bool,
 }
 
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ResolutionThunk {
     entries: Vec<ResolutionThunkEntry>,
     distance: Option<f64>,
     gui_trash_path: Option<PathBuf>,
+    move_dups_to: Option<PathBuf>,
+    search_dirs: Vec<PathBuf>,
+    gui_archive_path: Option<PathBuf>,
+    match_image_layout: MatchImageLayout,
+    keeper_rules: KeeperRules,
+    ladder_order: bool,
+    dry_run: bool,
 }
 
 impl ResolutionThunk {
-    #[cfg(all(target_family = "unix", feature = "gui"))]
     pub fn from_matchgroup(
-        match_group: &MatchGroup,
+        match_group: &DupGroup,
+        cache: &VideoHashFilesystemCache,
+        cfg: &AppCfg,
+    ) -> Self {
+        Self::from_paths(
+            match_group.reference(),
+            match_group.duplicates(),
+            cache,
+            cfg,
+        )
+    }
+
+    pub fn from_paths<'a>(
+        reference: Option<&Path>,
+        duplicates: impl IntoIterator<Item = &'a Path>,
         cache: &VideoHashFilesystemCache,
-        gui_trash_path: &Option<PathBuf>,
+        cfg: &AppCfg,
     ) -> Self {
+        let output_cfg = &cfg.output_cfg;
         let mut thunk = Self {
             entries: Default::default(),
             distance: Default::default(),
-            gui_trash_path: gui_trash_path.clone(),
+            gui_trash_path: output_cfg.gui_trash_path.clone(),
+            move_dups_to: output_cfg.move_dups_to.clone(),
+            search_dirs: cfg
+                .dir_cfg
+                .cand_dirs
+                .iter()
+                .chain(cfg.dir_cfg.ref_dirs.iter())
+                .cloned()
+                .collect(),
+            gui_archive_path: output_cfg.gui_archive_path.clone(),
+            match_image_layout: output_cfg.match_image_layout(),
+            keeper_rules: cfg.keeper_rules(KeeperPolicy::BestQuality),
+            ladder_order: output_cfg.ladder_order,
+            dry_run: cfg.dry_run,
         };
 
         //first add the reference, if it exists...
-        if let Some(ref reference) = match_group.reference() {
-            let ref_stats = cache.fetch_stats(reference).unwrap();
-            thunk.insert_reference(reference.to_path_buf(), ref_stats);
+        if let Some(reference) = reference {
+            let ref_stats = Self::fetch_stats_or_default(cache, reference);
+            thunk.insert_reference(
+                reference.to_path_buf(),
+                ref_stats,
+                subtitles::has_subtitles(reference),
+            );
         }
 
-        for entry in match_group.duplicates() {
-            thunk.insert_entry(entry.to_path_buf(), cache.fetch_stats(entry).unwrap());
+        for entry in duplicates {
+            thunk.insert_entry(
+                entry.to_path_buf(),
+                Self::fetch_stats_or_default(cache, entry),
+                subtitles::has_subtitles(entry),
+            );
         }
 
         thunk.populate_distance(cache);
@@ -146,30 +154,61 @@ impl ResolutionThunk {
         thunk
     }
 
+    //A file whose stats could not be calculated can still be matched, so it should still be shown
+    //(with empty stats) rather than bringing down the whole GUI.
+    fn fetch_stats_or_default(cache: &VideoHashFilesystemCache, src_path: &Path) -> VideoStats {
+        match cache.fetch_stats(src_path) {
+            Ok(stats) => stats,
+            Err(e) => {
+                warn!("Failed to fetch stats for {}: {}", src_path.display(), e);
+                VideoStats::default()
+            }
+        }
+    }
+
+    #[cfg(all(target_family = "unix", feature = "gui"))]
     pub fn len(&self) -> usize {
         self.entries.len()
     }
 
-    fn insert_entry(&mut self, filename: PathBuf, stats: VideoStats) {
+    fn insert_entry(&mut self, filename: PathBuf, stats: VideoStats, has_subtitles: bool) {
         self.entries.push(ResolutionThunkEntry {
             filename,
             is_reference: false,
             hash: None,
             stats,
+            has_subtitles,
         });
-        self.entries
-            .sort_by_key(|x| (!x.is_reference, x.filename.as_os_str().len()));
+        self.sort_entries();
     }
 
-    fn insert_reference(&mut self, filename: PathBuf, stats: VideoStats) {
+    fn insert_reference(&mut self, filename: PathBuf, stats: VideoStats, has_subtitles: bool) {
         self.entries.push(ResolutionThunkEntry {
             filename,
             is_reference: true,
             hash: None,
             stats,
+            has_subtitles,
+        });
+        self.sort_entries();
+    }
+
+    //References first, then (with --ladder-order) the highest resolutions, then the shortest paths.
+    fn sort_entries(&mut self) {
+        let ladder_order = self.ladder_order;
+        self.entries.sort_by_key(|x| {
+            let (width, height) = x.stats.resolution();
+            let pixels = if ladder_order {
+                u64::from(width) * u64::from(height)
+            } else {
+                0
+            };
+            (
+                !x.is_reference,
+                std::cmp::Reverse(pixels),
+                x.filename.as_os_str().len(),
+            )
         });
-        self.entries
-            .sort_by_key(|x| (!x.is_reference, x.filename.as_os_str().len()));
     }
 
     fn populate_distance(&mut self, cache: &VideoHashFilesystemCache) {
@@ -207,14 +246,13 @@ impl ResolutionThunk {
             .collect::<Vec<_>>()
     }
 
-    pub fn hash(&self, src_path: &Path) -> VideoHash {
+    #[cfg(all(target_family = "unix", feature = "gui"))]
+    //The hash of an entry, unless it could not be fetched from the cache.
+    pub fn hash(&self, src_path: &Path) -> Option<VideoHash> {
         self.entries
             .iter()
             .find(|x| x.filename == src_path)
-            .unwrap()
-            .clone()
-            .hash
-            .unwrap()
+            .and_then(|x| x.hash.clone())
     }
 
     pub fn calc_winning_stats(&self, filename: &Path) -> WinningStats {
@@ -265,6 +303,12 @@ impl ResolutionThunk {
             .iter()
             .all(|e| e.stats.has_audio() == best_has_audio);
 
+        let best_has_subtitles = self.entries.iter().any(|e| e.has_subtitles);
+        let has_subtitles_all_eq = self
+            .entries
+            .iter()
+            .all(|e| e.has_subtitles == best_has_subtitles);
+
         let current_entry = self
             .entries
             .iter()
@@ -279,6 +323,54 @@ impl ResolutionThunk {
             res: current_stats.resolution() == best_res && !res_all_eq,
             bitrate: current_stats.bit_rate() == best_bitrate && !bitrate_all_eq,
             has_audio: current_stats.has_audio() == best_has_audio && !has_audio_all_eq,
+            has_subtitles: current_entry.has_subtitles == best_has_subtitles
+                && !has_subtitles_all_eq,
+        }
+    }
+
+    //The index of the entry with the best resolution, breaking ties by bitrate and then filesize.
+    //Protected entries (and with --prefer-subtitles, entries with subtitles) are preferred over all others.
+    pub fn best_entry_idx(&self) -> Option<usize> {
+        let paths = self.entries();
+        let stats = self
+            .entries
+            .iter()
+            .map(|e| e.stats.clone())
+            .collect::<Vec<_>>();
+        resolution_plan::choose_keeper(&self.keeper_rules, &paths, &stats)
+    }
+
+    //The indexes of the entries which may be kept: the protected entries (including those in the keep list) if
+    //there are any, otherwise every entry. This is the same preference as resolution_plan::choose_keeper.
+    pub fn keeper_candidate_idxs(&self) -> Vec<usize> {
+        let protected_idxs = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_idx, entry)| {
+                resolution_plan::is_protected(&entry.filename, &self.keeper_rules)
+            })
+            .map(|(idx, _entry)| idx)
+            .collect::<Vec<_>>();
+
+        if protected_idxs.is_empty() {
+            (0..self.entries.len()).collect()
+        } else {
+            protected_idxs
+        }
+    }
+
+    //The value of an entry by an --auto-resolve metric, where larger is better.
+    pub fn metric_value(&self, idx: usize, metric: AutoResolveMetric) -> u64 {
+        let stats = &self.entries[idx].stats;
+        match metric {
+            AutoResolveMetric::LargestRes => {
+                let (x, y) = stats.resolution();
+                x as u64 * y as u64
+            }
+            AutoResolveMetric::LargestFile => stats.size(),
+            AutoResolveMetric::LargestPngsize => stats.png_size,
+            AutoResolveMetric::HighestBitrate => stats.bit_rate(),
         }
     }
 
@@ -311,9 +403,13 @@ impl ResolutionThunk {
         let filesize = byte_unit::Byte::from_bytes(stats.size() as u128);
         let filesize = filesize.get_appropriate_unit(false);
 
-        let pngsize = stats.png_size();
-        let pngsize = byte_unit::Byte::from_bytes(pngsize as u128);
-        let pngsize = pngsize.get_appropriate_unit(false);
+        //A png size of 0 means it was never calculated.
+        let pngsize = match stats.png_size() {
+            0 => "n/a".to_string(),
+            pngsize => byte_unit::Byte::from_bytes(pngsize as u128)
+                .get_appropriate_unit(false)
+                .to_string(),
+        };
 
         format!("f_sz: {:>9}, p_sz: {:>9}", filesize, pngsize,)
     }
@@ -440,17 +536,42 @@ impl ResolutionThunk {
         let choice = self.parse_choice(choice)?;
         self.validate_choice(&choice)?;
 
+        let ops = self.plan(&choice)?;
+
+        //Once the files are trashed it is no longer possible to render them, so if the user wants a record of
+        //what was resolved, write it out now.
+        if let Some(archive_dir) = &self.gui_archive_path {
+            if !self.dry_run {
+                self.archive_thumbnails(archive_dir, &self.entries[choice.contents_idx].filename);
+            }
+        }
+
+        debug!("Executing resolution: {:?}", ops);
+        resolution_plan::execute(&ops, self.dry_run)?;
+
+        Ok(())
+    }
+
+    //The operations which carry out a validated choice, without touching the filesystem.
+    fn plan(&self, choice: &ResolutionInstruction) -> Result<Vec<PlannedOp>, ResolutionError> {
         let ResolutionInstruction {
             basename_idx,
             dirname_idx,
             contents_idx,
-        } = choice;
+        } = *choice;
 
         let dirname_entry = &self.entries[dirname_idx];
         let contents_entry = &self.entries[contents_idx];
         let basename_entry = &self.entries[basename_idx];
 
-        let entries_to_trash = self.entries.iter().filter(|&entry| entry != contents_entry);
+        //Protected files (including those in the keep list) survive whichever entry is chosen.
+        let is_protected = |entry: &ResolutionThunkEntry| {
+            resolution_plan::is_protected(&entry.filename, &self.keeper_rules)
+        };
+        let entries_to_trash = self
+            .entries
+            .iter()
+            .filter(|&entry| entry != contents_entry && !is_protected(entry));
 
         //If the contents_entry is to be renamed, get the new name.
         let new_name;
@@ -478,156 +599,128 @@ impl ResolutionThunk {
             ));
         }
 
-        //now trash all other entries (ignoring contents_entry)
-        debug!("Trashing all files except contents_entry");
+        //now trash all other entries (ignoring contents_entry), then if necessary move contents_entry to
+        //the dir of dirname_entry with the name of basename_entry.
+        let mut ops = vec![PlannedOp::Keep(contents_entry.filename.clone())];
+        for entry in self
+            .entries
+            .iter()
+            .filter(|&entry| entry != contents_entry && is_protected(entry))
+        {
+            ops.push(PlannedOp::Keep(entry.filename.clone()));
+        }
         for entry in entries_to_trash {
-            self.trash_file(&entry.filename)?;
+            ops.push(match &self.move_dups_to {
+                Some(move_dups_to) => {
+                    resolution_plan::relocate_op(move_dups_to, &self.search_dirs, &entry.filename)?
+                }
+                None => resolution_plan::trash_op(self.gui_trash_path.as_deref(), &entry.filename)?,
+            });
         }
-
         if need_to_move_contents {
-            debug!("Moving contents_entry to dir of dirname_entry with name of basename_entry");
-            move_path(&contents_entry.filename, &new_name)?;
-        }
-
-        Ok(())
-    }
-
-    fn get_trash_path(&self, p: &Path) -> Result<PathBuf, TrashError> {
-        let relative_filename = p.strip_prefix("/")?;
-        self.gui_trash_path
-            .as_ref()
-            .map(|p| p.join(relative_filename))
-            .ok_or(NoTrashPathError)
-    }
-
-    fn trash_file(&self, old_path: &Path) -> Result<(), TrashError> {
-        fn is_already_trashed(old_path: &Path, trash_path: &Path) -> Result<bool, TrashError> {
-            //If there is no file in the trash path, then it is not already trashed.
-            if !trash_path.exists() {
-                return Ok(false);
-            }
-
-            fn sha2_file(path: &Path) -> Result<[u8; 32], TrashError> {
-                use sha2::Digest;
-
-                let mut file = match std::fs::File::open(&path) {
-                    Ok(file) => Ok(file),
-                    Err(e) => Err(TrashError::FileOpenError(
-                        path.to_string_lossy().to_string(),
-                        e,
-                    )),
-                }?;
-                let mut hasher = sha2::Sha256::new();
-
-                match std::io::copy(&mut file, &mut hasher) {
-                    Ok(_) => Ok(hasher.finalize().into()),
-                    Err(e) => Err(TrashError::IoError(path.to_string_lossy().to_string(), e)),
-                }
-            }
-
-            Ok(sha2_file(old_path)? == sha2_file(trash_path)?)
+            ops.push(PlannedOp::Move {
+                src: contents_entry.filename.clone(),
+                dest: new_name,
+            });
         }
 
-        let new_path = self.get_trash_path(old_path)?;
-
-        println!("trashing {}", old_path.display());
-
-        match is_already_trashed(old_path, &new_path)? {
-            true => delete_path(old_path)?,
-            false => move_path(old_path, &new_path)?,
+        if self.keeper_rules.require_readable {
+            resolution_plan::check_keepers_readable(&ops)?;
         }
 
-        Ok(())
+        Ok(ops)
     }
-}
-
-fn delete_path(path: &Path) -> Result<(), TrashError> {
-    println!("Deleting {}", path.display());
-
-    if let Err(e) = std::fs::remove_file(&path) {
-        let e = DeleteFileFailure(path.to_string_lossy().to_string(), e);
-        return Err(e);
-    };
 
-    Ok(())
-}
-
-fn move_path(source: &Path, dest: &Path) -> Result<(), TrashError> {
-    println!("Moving {} ------> {}", source.display(), dest.display());
+    fn archive_thumbnails(&self, archive_dir: &Path, kept_path: &Path) {
+        let mut archive_name = kept_path.file_stem().unwrap_or_default().to_os_string();
+        archive_name.push(".png");
+        let archive_path = get_new_name_if_path_already_exists(&archive_dir.join(archive_name));
 
-    if !source.exists() {
-        return Err(SourceFileMissing(source.to_string_lossy().to_string()));
+        let reference = self
+            .entries
+            .iter()
+            .find(|e| e.is_reference)
+            .map(|e| e.filename.as_path());
+        let duplicates = self
+            .entries
+            .iter()
+            .filter(|e| !e.is_reference)
+            .map(|e| e.filename.as_path());
+
+        write_image(
+            reference,
+            duplicates,
+            &archive_path,
+            &match_image_font(),
+            &self.match_image_layout,
+        );
     }
+}
 
-    let dest = get_new_name_if_path_already_exists(dest);
-
-    #[allow(clippy::question_mark)] //spurious
-    match dest.parent() {
-        Some(parent_dir) => {
-            if std::fs::create_dir_all(parent_dir).is_err() {
-                return Err(CreateParentDirFailure(
-                    parent_dir.to_string_lossy().to_string(),
-                ));
-            }
-        }
-        None => {
-            return Err(ExtractParentDirFailure(dest.to_string_lossy().to_string()));
-        }
-    };
-
-    if let Err(e) = std::fs::rename(&source, &dest) {
-        match e.raw_os_error() {
-            Some(libc::EPERM) | Some(libc::EXDEV) => {
-                //try copy and delete.
-                println!("Unable to move. Performing copy and delete instead.");
-                if let Err(_e) = std::fs::copy(&source, &dest) {
-                    let e = CopyFailError(
-                        source.to_string_lossy().to_string(),
-                        dest.to_string_lossy().to_string(),
-                    );
-                    return Err(e);
-                };
-                delete_path(source)?;
-            }
-            Some(_) => {
-                let e = UnhandledError(
-                    source.to_string_lossy().to_string(),
-                    dest.to_string_lossy().to_string(),
-                );
-                return Err(e);
-            }
-            None => {
-                let e = RenameNoneError(
-                    source.to_string_lossy().to_string(),
-                    dest.to_string_lossy().to_string(),
-                );
-                return Err(e);
-            }
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn protected_files_are_never_trashed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let archive_dir = tempdir.path().join("archive");
+        std::fs::create_dir(&archive_dir).unwrap();
+        let kept_path = tempdir.path().join("kept.mp4");
+        let protected_path = archive_dir.join("protected.mp4");
+        let other_path = tempdir.path().join("other.mp4");
+        for path in [&kept_path, &protected_path, &other_path] {
+            std::fs::write(path, b"contents").unwrap();
         }
-    }
 
-    Ok(())
-}
+        let entry = |filename: &Path| ResolutionThunkEntry {
+            filename: filename.to_path_buf(),
+            ..Default::default()
+        };
+        let thunk = ResolutionThunk {
+            entries: vec![
+                entry(&kept_path),
+                entry(&protected_path),
+                entry(&other_path),
+            ],
+            distance: None,
+            gui_trash_path: None,
+            move_dups_to: None,
+            search_dirs: vec![],
+            gui_archive_path: None,
+            match_image_layout: MatchImageLayout {
+                thumb_size: 100,
+                num_frames: 1,
+                fps: "1".to_string(),
+            },
+            keeper_rules: KeeperRules {
+                policy: KeeperPolicy::BestQuality,
+                protect_dirs: vec![archive_dir],
+                keep_list: HashSet::new(),
+                prefer_subtitles: false,
+                require_readable: false,
+            },
+            ladder_order: false,
+            dry_run: true,
+        };
 
-//with a given path, check if it already exists on the filesystem.
-//If it does, append a suffix that does not exist (in the form "(1)" or "(2)" etc..)
-//until a filename is found that does exist. Then return the new name.
-fn get_new_name_if_path_already_exists(p: &Path) -> PathBuf {
-    let original_stem = p.file_stem().unwrap();
-    let extension = p.extension();
-
-    let mut ret = p.to_path_buf();
-    let mut counter = 1u64;
-    while ret.exists() {
-        let mut new_file_stem = original_stem.to_os_string();
-        new_file_stem.push(OsString::from(format!(" ({})", counter)));
-        ret.set_file_name(new_file_stem);
-        if let Some(ref extension) = extension {
-            ret.set_extension(extension);
-        }
+        let ops = thunk
+            .plan(&ResolutionInstruction {
+                basename_idx: 0,
+                dirname_idx: 0,
+                contents_idx: 0,
+            })
+            .unwrap();
 
-        counter += 1;
+        assert_eq!(
+            ops,
+            vec![
+                PlannedOp::Keep(kept_path),
+                PlannedOp::Keep(protected_path),
+                PlannedOp::SystemTrash(other_path),
+            ]
+        );
     }
-
-    ret
 }
@@ -0,0 +1,45 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::app::*;
+
+//A group of duplicates as written by --json.
+#[derive(Debug, Deserialize)]
+pub struct SavedGroup {
+    pub reference: Option<PathBuf>,
+    pub duplicates: Vec<PathBuf>,
+}
+
+//Results are saved either as the list of groups printed by --json, or as a whole SearchReport.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SavedResults {
+    Groups(Vec<SavedGroup>),
+    Report { groups: Vec<SavedGroup> },
+}
+
+//Read groups of duplicates which were saved from an earlier search.
+pub fn load_results(results_file: &Path) -> Result<Vec<SavedGroup>, AppError> {
+    let contents = std::fs::read_to_string(results_file)
+        .map_err(|e| AppError::ResultsFileReadError(results_file.to_path_buf(), e))?;
+
+    let saved_results = serde_json::from_str(&contents)
+        .map_err(|e| AppError::ResultsFileParseError(results_file.to_path_buf(), e))?;
+
+    match saved_results {
+        SavedResults::Groups(groups) | SavedResults::Report { groups } => Ok(groups),
+    }
+}
+
+//Every path in groups of duplicates saved from an earlier search. Files which have been deleted since are
+//still included, but they can't be found by a new search so they have no effect.
+pub fn load_baseline_paths(baseline_file: &Path) -> Result<HashSet<PathBuf>, AppError> {
+    Ok(load_results(baseline_file)?
+        .into_iter()
+        .flat_map(|group| group.reference.into_iter().chain(group.duplicates))
+        .collect())
+}
@@ -0,0 +1,113 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+//The files found by an enumeration, along with the --files and --with-refs that were enumerated. An
+//enumeration can only be resumed by a run with the same --files and --with-refs.
+#[derive(Debug, Serialize, Deserialize)]
+struct Enumeration {
+    cand_dirs: Vec<PathBuf>,
+    ref_dirs: Vec<PathBuf>,
+    src_paths: HashSet<PathBuf>,
+}
+
+//The files found by the last enumeration of --files and --with-refs are stored in a small file beside the cache,
+//until the cache has been updated with all of them.
+fn enumeration_file_path(cache_path: &Path) -> PathBuf {
+    let mut file_name = cache_path
+        .file_name()
+        .map(OsString::from)
+        .unwrap_or_default();
+    file_name.push(".enumeration");
+    cache_path.with_file_name(file_name)
+}
+
+//Record the files found by an enumeration, so that if the cache update is interrupted, it can be resumed
+//with --resume without enumerating again.
+pub fn save_enumeration<'a>(
+    cache_path: &Path,
+    cand_dirs: &[PathBuf],
+    ref_dirs: &[PathBuf],
+    src_paths: impl IntoIterator<Item = &'a PathBuf>,
+) {
+    let enumeration_path = enumeration_file_path(cache_path);
+    let enumeration = Enumeration {
+        cand_dirs: cand_dirs.to_vec(),
+        ref_dirs: ref_dirs.to_vec(),
+        src_paths: src_paths.into_iter().cloned().collect(),
+    };
+
+    let result = serde_json::to_vec(&enumeration)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| fs::write(&enumeration_path, contents).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        warn!(
+            "Failed to record enumerated files at {}: {}",
+            enumeration_path.display(),
+            e
+        );
+    }
+}
+
+//The files found by an enumeration whose cache update did not finish, if there was one and it was of the
+//same --files and --with-refs.
+pub fn load_enumeration(
+    cache_path: &Path,
+    cand_dirs: &[PathBuf],
+    ref_dirs: &[PathBuf],
+) -> Option<HashSet<PathBuf>> {
+    let enumeration_path = enumeration_file_path(cache_path);
+
+    let contents = match fs::read(&enumeration_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!(
+                "Failed to read enumerated files at {}: {}",
+                enumeration_path.display(),
+                e
+            );
+            return None;
+        }
+    };
+
+    match serde_json::from_slice::<Enumeration>(&contents) {
+        Ok(enumeration)
+            if enumeration.cand_dirs == cand_dirs && enumeration.ref_dirs == ref_dirs =>
+        {
+            Some(enumeration.src_paths)
+        }
+        Ok(_enumeration) => {
+            info!("--files or --with-refs have changed since the interrupted cache update, so it can't be resumed. Searching for files again");
+            None
+        }
+        Err(e) => {
+            warn!(
+                "Failed to parse enumerated files at {}: {}",
+                enumeration_path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+//Once the cache has been updated with every enumerated file there is nothing left to resume.
+pub fn remove_enumeration(cache_path: &Path) {
+    let enumeration_path = enumeration_file_path(cache_path);
+    match fs::remove_file(&enumeration_path) {
+        Ok(()) => (),
+        Err(e) if e.kind() == ErrorKind::NotFound => (),
+        Err(e) => warn!(
+            "Failed to remove enumerated files at {}: {}",
+            enumeration_path.display(),
+            e
+        ),
+    }
+}
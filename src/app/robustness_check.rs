@@ -0,0 +1,123 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use vid_dup_finder_lib::*;
+use video_hash_filesystem_cache::*;
+
+use crate::app::*;
+
+//The transformations applied to the source file, as a description and the ffmpeg arguments that perform it.
+const TRANSFORMS: &[(&str, &str, &[&str])] = &[
+    (
+        "scaled to half size",
+        "scaled",
+        &["-vf", "scale=trunc(iw/4)*2:-2"],
+    ),
+    ("re-encoded at 300kbit/s", "low_bitrate", &["-b:v", "300k"]),
+    (
+        "letterboxed",
+        "letterboxed",
+        &["-vf", "pad=iw:trunc(ih*1.2/2)*2:0:(oh-ih)/2"],
+    ),
+];
+
+//Create transformed copies of a file with ffmpeg, and print how far each one is from the original,
+//to show whether the given tolerance would match them.
+pub fn run_robustness_check(
+    src_path: &Path,
+    tolerance: NormalizedTolerance,
+) -> Result<Vec<AppError>, AppError> {
+    let work_dir = std::env::temp_dir().join(format!(
+        "vid_dup_finder_robustness_check_{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&work_dir)
+        .map_err(|e| AppError::RobustnessCheckIoError(work_dir.clone(), e))?;
+
+    let ret = run_robustness_check_inner(src_path, tolerance, &work_dir);
+
+    if let Err(e) = std::fs::remove_dir_all(&work_dir) {
+        warn!(
+            "Failed to remove temporary directory {}: {}",
+            work_dir.display(),
+            e
+        );
+    }
+
+    ret
+}
+
+fn run_robustness_check_inner(
+    src_path: &Path,
+    tolerance: NormalizedTolerance,
+    work_dir: &Path,
+) -> Result<Vec<AppError>, AppError> {
+    if !src_path.is_file() {
+        return Err(AppError::CandPathNotFoundError(src_path.to_path_buf()));
+    }
+
+    let mut transformed_paths = vec![];
+    for (description, file_stem, ffmpeg_args) in TRANSFORMS {
+        let dest_path = work_dir.join(format!("{}.mkv", file_stem));
+        info!("Creating copy of {} {}", src_path.display(), description);
+
+        let status = Command::new("ffmpeg")
+            .args(["-hide_banner", "-loglevel", "error", "-i"])
+            .arg(src_path)
+            .args(*ffmpeg_args)
+            .arg("-an")
+            .arg(&dest_path)
+            .status();
+
+        match status {
+            Ok(status) if status.success() => transformed_paths.push((*description, dest_path)),
+            Ok(status) => warn!("ffmpeg failed to create {} copy: {}", description, status),
+            Err(e) => warn!("ffmpeg failed to create {} copy: {}", description, e),
+        }
+    }
+
+    //Hash the original and its copies in a throwaway cache, so the user's cache is not polluted.
+    let cache = VideoHashFilesystemCache::new(100, work_dir.join("cache.bin"))?;
+    let srcs = vec![src_path.to_path_buf(), work_dir.to_path_buf()];
+    let mut projection = FileProjection::new(&srcs, Vec::<PathBuf>::new(), Vec::<OsString>::new())
+        .map_err(AppError::from_cand_exclusion_error)?;
+    let paths = std::iter::once(src_path.to_path_buf())
+        .chain(transformed_paths.iter().map(|(_, path)| path.clone()))
+        .collect::<HashSet<_>>();
+    projection.project_using_list(&paths);
+
+    let nonfatal_errs = cache
+        .update_using_fs(&projection)?
+        .into_iter()
+        .map(AppError::from)
+        .collect();
+
+    let src_hash = cache.fetch(src_path)?;
+
+    println!(
+        "{:<24} {:>8}  matches at tolerance {}",
+        "copy",
+        "distance",
+        tolerance.value()
+    );
+    for (description, path) in &transformed_paths {
+        match cache.fetch(path) {
+            Ok(hash) => {
+                let distance = src_hash.normalized_levenshtein_distance(&hash).value();
+                let matches = if distance <= tolerance.value() {
+                    "yes"
+                } else {
+                    "no"
+                };
+                println!("{:<24} {:>8.3}  {}", description, distance, matches);
+            }
+            Err(e) => warn!("Failed to hash {} copy: {}", description, e),
+        }
+    }
+
+    Ok(nonfatal_errs)
+}
@@ -0,0 +1,90 @@
+use std::{
+    collections::{hash_map::RandomState, HashSet},
+    path::PathBuf,
+};
+
+use video_hash_filesystem_cache::*;
+
+use crate::app::*;
+
+//Counts gathered over a run, printed at the end with --stats or --verbose.
+#[derive(Debug, Default)]
+pub struct RunStats {
+    update: Option<UpdateStats>,
+    num_searched: usize,
+    num_groups: usize,
+    reclaimable_bytes: u64,
+}
+
+#[derive(Debug)]
+struct UpdateStats {
+    num_enumerated: usize,
+    num_hashed: usize,
+    num_from_cache: usize,
+    num_unhashable: usize,
+    num_errors: usize,
+}
+
+impl RunStats {
+    //Record what a cache update did. The cached paths must be fetched before the update.
+    pub fn record_update(
+        &mut self,
+        enumerated: &HashSet<PathBuf, RandomState>,
+        previously_cached: &HashSet<PathBuf, RandomState>,
+        update_errs: &[AppError],
+        cache: &VideoHashFilesystemCache,
+    ) {
+        let num_from_cache = enumerated.intersection(previously_cached).count();
+        self.update = Some(UpdateStats {
+            num_enumerated: enumerated.len(),
+            num_hashed: enumerated.len() - num_from_cache,
+            num_from_cache,
+            num_unhashable: enumerated
+                .iter()
+                .filter(|src_path| cache.fetch(src_path).is_err())
+                .count(),
+            num_errors: update_errs
+                .iter()
+                .filter(|err| matches!(err, AppError::CacheErrror(_)))
+                .count(),
+        });
+    }
+
+    //Record the groups found by a search. If every video but the largest in each group were removed, then
+    //the sizes of the removed videos could be reclaimed.
+    pub fn record_search(
+        &mut self,
+        num_searched: usize,
+        groups: &[DupGroup],
+        cache: &VideoHashFilesystemCache,
+    ) {
+        self.num_searched = num_searched;
+        self.num_groups = groups.len();
+        self.reclaimable_bytes = groups
+            .iter()
+            .map(|group| search_output::reclaimable_bytes(group, cache))
+            .sum();
+    }
+
+    pub fn print(&self) {
+        if let Some(update) = &self.update {
+            info!(
+                "Found {} files: {} hashed this run, {} already in the cache. {} could not be hashed (not videos, too short, or errors), including {} errors this run",
+                update.num_enumerated,
+                update.num_hashed,
+                update.num_from_cache,
+                update.num_unhashable,
+                update.num_errors
+            );
+        }
+
+        let reclaimable =
+            byte_unit::Byte::from_bytes(self.reclaimable_bytes as u128).get_appropriate_unit(false);
+        info!(
+            "Searched {} files and found {} groups of duplicates. Removing all but the largest file in each group would free {}",
+            self.num_searched,
+            self.num_groups,
+            reclaimable
+        );
+    }
+}
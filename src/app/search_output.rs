@@ -1,25 +1,579 @@
-use std::path::Path;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
+use serde::Serialize;
 use vid_dup_finder_lib::*;
+use video_hash_filesystem_cache::*;
+
+use super::audio_hash;
+use crate::app::*;
 
 // #[cfg(all(target_family = "unix", feature = "gui"))]
 // use super::match_group_resolution_thunk::ResolutionThunk;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GroupKind {
+    //Every file in the group has the same contents, so they are copies of one file under different names.
+    Rename,
+    //The files look the same but have different contents, so they need to be reviewed.
+    NearDuplicate,
+    //Every file in the group has the same contents, which was found by --exact-first without searching.
+    Exact,
+}
+
+impl GroupKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            GroupKind::Rename => "rename",
+            GroupKind::NearDuplicate => "near-duplicate",
+            GroupKind::Exact => "exact",
+        }
+    }
+}
+
+//A group of duplicates. Groups are found by the library's search as MatchGroups, but the application sometimes
+//needs to build groups of its own (e.g. when merging overlapping groups with --transitive), which MatchGroup
+//does not allow.
+#[derive(Debug, Clone)]
+pub struct DupGroup {
+    reference: Option<PathBuf>,
+    duplicates: Vec<PathBuf>,
+    exact: bool,
+}
+
+impl DupGroup {
+    //A group of files with identical contents.
+    pub fn exact(reference: Option<PathBuf>, duplicates: Vec<PathBuf>) -> Self {
+        Self {
+            reference,
+            duplicates,
+            exact: true,
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.exact
+    }
+
+    pub fn reference(&self) -> Option<&Path> {
+        self.reference.as_deref()
+    }
+
+    pub fn duplicates(&self) -> impl Iterator<Item = &Path> {
+        self.duplicates.iter().map(PathBuf::as_path)
+    }
+}
+
+impl From<MatchGroup> for DupGroup {
+    fn from(group: MatchGroup) -> Self {
+        Self {
+            reference: group.reference().map(Path::to_path_buf),
+            duplicates: group.duplicates().map(Path::to_path_buf).collect(),
+            exact: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SearchOutput {
-    dup_groups: Vec<MatchGroup>,
+    dup_groups: Vec<DupGroup>,
+    group_kinds: Option<Vec<GroupKind>>,
 }
 
 impl SearchOutput {
-    pub fn new(dup_groups: Vec<MatchGroup>) -> Self {
-        Self { dup_groups }
+    pub fn new(dup_groups: Vec<DupGroup>) -> Self {
+        Self {
+            dup_groups,
+            group_kinds: None,
+        }
     }
 
-    pub fn dup_groups(&self) -> impl Iterator<Item = &MatchGroup> {
+    pub fn dup_groups(&self) -> impl Iterator<Item = &DupGroup> {
         self.dup_groups.iter()
     }
 
     pub fn dup_paths(&self) -> impl Iterator<Item = &Path> {
         self.dup_groups.iter().flat_map(|group| group.duplicates())
     }
+
+    pub fn group_kind(&self, group_idx: usize) -> Option<GroupKind> {
+        self.group_kinds
+            .as_ref()
+            .and_then(|kinds| kinds.get(group_idx).copied())
+    }
+
+    pub fn classify_renames(&mut self, cache: &VideoHashFilesystemCache) {
+        let kinds = self
+            .dup_groups
+            .iter()
+            .map(|group| classify_group(group, cache))
+            .collect();
+
+        self.group_kinds = Some(kinds);
+    }
+}
+
+//The results of a search in the form they are reported: each group with its duplicates in output order, and the
+//unique files. Every output format is printed from this, so it can also be used without printing anything.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchReport<'a> {
+    pub groups: Vec<ReportGroup<'a>>,
+    pub unique: Vec<&'a Path>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportGroup<'a> {
+    pub reference: Option<&'a Path>,
+    pub duplicates: Vec<&'a Path>,
+    pub distance: f64,
+    pub kind: Option<GroupKind>,
+    pub ref_set: Option<&'a str>,
+}
+
+impl<'a> SearchReport<'a> {
+    pub fn new(
+        search_output: &'a SearchOutput,
+        unique_paths: &[&'a Path],
+        cache: &VideoHashFilesystemCache,
+        app_cfg: &'a AppCfg,
+    ) -> Self {
+        let groups = search_output
+            .dup_groups()
+            .enumerate()
+            .map(|(i, group)| ReportGroup {
+                reference: group.reference(),
+                duplicates: ordered_duplicates(group, cache, &app_cfg.output_cfg),
+                distance: max_distance(group, cache),
+                kind: if group.is_exact() {
+                    Some(GroupKind::Exact)
+                } else {
+                    search_output.group_kind(i)
+                },
+                ref_set: ref_set_name(group, app_cfg),
+            })
+            .collect();
+
+        Self {
+            groups,
+            unique: unique_paths.to_vec(),
+        }
+    }
+}
+
+impl ReportGroup<'_> {
+    //How alike the least alike members of the group are, from 0 (nothing alike) to 1 (identical hashes).
+    pub fn similarity(&self) -> f64 {
+        1.0 - self.distance
+    }
+}
+
+//The name of the --ref-set that the reference of this group came from, if any.
+fn ref_set_name<'a>(group: &DupGroup, app_cfg: &'a AppCfg) -> Option<&'a str> {
+    group
+        .reference()
+        .and_then(|reference| app_cfg.dir_cfg.ref_set_name(reference))
+}
+
+pub fn group_paths(group: &DupGroup) -> impl Iterator<Item = &Path> {
+    group.reference().into_iter().chain(group.duplicates())
+}
+
+//The duplicates of a group, from the highest resolution to the lowest with --ladder-order, or otherwise in the
+//order they were found. Files with the same resolution stay in the order they were found.
+pub fn ordered_duplicates<'a>(
+    group: &'a DupGroup,
+    cache: &VideoHashFilesystemCache,
+    output_cfg: &OutputCfg,
+) -> Vec<&'a Path> {
+    let mut duplicates = group.duplicates().collect::<Vec<_>>();
+    if output_cfg.ladder_order {
+        duplicates.sort_by_cached_key(|dup_path| {
+            let (x, y) = cache
+                .fetch_stats(dup_path)
+                .map(|stats| stats.resolution())
+                .unwrap_or_default();
+            std::cmp::Reverse(u64::from(x) * u64::from(y))
+        });
+    }
+    duplicates
+}
+
+//The largest distance between any two members of the group. (This is what the GUI displays for a group)
+pub fn max_distance(group: &DupGroup, cache: &VideoHashFilesystemCache) -> f64 {
+    use itertools::Itertools;
+    group_paths(group)
+        .filter_map(|src_path| cache.fetch(src_path).ok())
+        .combinations(2)
+        .fold(0.0, |max_distance, pair| {
+            let current_distance = pair[0].normalized_levenshtein_distance(&pair[1]).value();
+            current_distance.max(max_distance)
+        })
+}
+
+//Sort groups by the position in input_paths of their earliest member. Members which do not come from
+//input_paths (i.e. references) do not affect the order. The sort is stable, so groups whose earliest members
+//come from the same input path stay in the order they were found.
+pub fn sort_by_input_order(groups: &mut [DupGroup], input_paths: &[PathBuf]) {
+    let input_idx = |src_path: &Path| {
+        input_paths
+            .iter()
+            .position(|input_path| src_path.starts_with(input_path))
+    };
+
+    groups.sort_by_cached_key(|group| {
+        group_paths(group)
+            .filter_map(input_idx)
+            .min()
+            .unwrap_or(usize::MAX)
+    });
+}
+
+//Sort groups by the given key. Ties are broken by the first path in each group.
+pub fn sort_groups(groups: &mut [DupGroup], key: GroupSortKey, cache: &VideoHashFilesystemCache) {
+    use std::cmp::Reverse;
+
+    let first_path = |group: &DupGroup| group_paths(group).min().map(Path::to_path_buf);
+    match key {
+        //Distances are floats, so they are compared in thousandths to give a total order.
+        GroupSortKey::Similarity => groups.sort_by_cached_key(|group| {
+            let distance = (max_distance(group, cache) * 1000.0) as u64;
+            (distance, first_path(group))
+        }),
+        GroupSortKey::Size => groups.sort_by_cached_key(|group| {
+            (Reverse(reclaimable_bytes(group, cache)), first_path(group))
+        }),
+        GroupSortKey::Path => groups.sort_by_cached_key(|group| first_path(group)),
+        GroupSortKey::GroupSize => groups
+            .sort_by_cached_key(|group| (Reverse(group_paths(group).count()), first_path(group))),
+    }
+}
+
+//The space that would be freed by removing every file in the group except the largest.
+pub fn reclaimable_bytes(group: &DupGroup, cache: &VideoHashFilesystemCache) -> u64 {
+    let sizes = group_paths(group)
+        .map(|src_path| {
+            cache
+                .fetch_stats(src_path)
+                .map(|stats| stats.size())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    sizes.iter().sum::<u64>() - sizes.iter().max().copied().unwrap_or_default()
+}
+
+//Merge groups which share a member, so that if A matches B and B matches C then A, B and C are reported in one
+//group even when A and C are too different to match each other. Groups are ordered by their first member, and
+//members are kept in the order they were found. Only used for searches without references.
+pub fn merge_transitive(groups: Vec<DupGroup>) -> Vec<DupGroup> {
+    fn find(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    //Give each distinct path an index, in the order paths are first found.
+    let mut path_idxs = HashMap::new();
+    let mut paths = vec![];
+    for src_path in groups.iter().flat_map(|group| group.duplicates()) {
+        path_idxs.entry(src_path).or_insert_with(|| {
+            paths.push(src_path.to_path_buf());
+            paths.len() - 1
+        });
+    }
+
+    //Join every member of a group to the first member of that group.
+    let mut parents = (0..paths.len()).collect::<Vec<_>>();
+    for group in &groups {
+        let mut member_idxs = group.duplicates().map(|src_path| path_idxs[src_path]);
+        if let Some(first_idx) = member_idxs.next() {
+            for member_idx in member_idxs {
+                let (root_a, root_b) = (
+                    find(&mut parents, first_idx),
+                    find(&mut parents, member_idx),
+                );
+                parents[root_a.max(root_b)] = root_a.min(root_b);
+            }
+        }
+    }
+
+    //The root of each component is its earliest path, so building groups in path order keeps the order stable.
+    let mut component_idxs = HashMap::new();
+    let mut merged: Vec<DupGroup> = vec![];
+    for (i, src_path) in paths.into_iter().enumerate() {
+        let root = find(&mut parents, i);
+        let component_idx = *component_idxs.entry(root).or_insert_with(|| {
+            merged.push(DupGroup {
+                reference: None,
+                duplicates: vec![],
+                exact: false,
+            });
+            merged.len() - 1
+        });
+        merged[component_idx].duplicates.push(src_path);
+    }
+
+    merged
+}
+
+//Check groups which look identical (a distance of 0) by their contents. Such a group is split into groups of
+//members with the same contents, and members whose contents match no other member are returned separately, as
+//they can't be trusted. Groups with a nonzero distance are not checked.
+pub fn verify_identical(
+    groups: Vec<DupGroup>,
+    cache: &VideoHashFilesystemCache,
+) -> (Vec<DupGroup>, Vec<PathBuf>) {
+    let mut verified = vec![];
+    let mut unverified = vec![];
+    for group in groups {
+        if group.is_exact() || max_distance(&group, cache) > 0.0 {
+            verified.push(group);
+            continue;
+        }
+
+        let (split_groups, leftovers) = split_by_contents(&group);
+        verified.extend(split_groups);
+        unverified.extend(leftovers);
+    }
+
+    (verified, unverified)
+}
+
+//Split a group into groups of members with the same contents, in the order they were found. The reference stays
+//the reference of the members which share its contents. Members whose contents match no other member (or which
+//can't be read) are returned separately.
+fn split_by_contents(group: &DupGroup) -> (Vec<DupGroup>, Vec<PathBuf>) {
+    let mut by_digest: Vec<([u8; 32], Vec<&Path>)> = vec![];
+    let mut leftovers = vec![];
+    for src_path in group_paths(group) {
+        let digest = match content_hash::sha256_file(src_path) {
+            Ok(digest) => digest,
+            Err(e) => {
+                warn!("Failed to read {}: {}", src_path.display(), e);
+                leftovers.push(src_path.to_path_buf());
+                continue;
+            }
+        };
+
+        match by_digest
+            .iter_mut()
+            .find(|(class_digest, _paths)| *class_digest == digest)
+        {
+            Some((_digest, paths)) => paths.push(src_path),
+            None => by_digest.push((digest, vec![src_path])),
+        }
+    }
+
+    let mut split_groups = vec![];
+    for (_digest, paths) in by_digest {
+        if paths.len() < 2 {
+            leftovers.extend(paths.into_iter().map(Path::to_path_buf));
+            continue;
+        }
+
+        let reference = group
+            .reference()
+            .filter(|reference| paths.contains(reference));
+        split_groups.push(DupGroup {
+            reference: reference.map(Path::to_path_buf),
+            duplicates: paths
+                .into_iter()
+                .filter(|src_path| Some(*src_path) != reference)
+                .map(Path::to_path_buf)
+                .collect(),
+            exact: false,
+        });
+    }
+
+    (split_groups, leftovers)
+}
+
+//Split groups so that the members of each group also have matching audio. In a group with a reference,
+//duplicates whose audio does not match the reference are dropped. Otherwise the members are split into
+//groups which share the same audio. Members without audio (or whose audio can't be read) can't be compared,
+//so they are kept wherever they were matched.
+pub fn affirm_audio(
+    groups: Vec<DupGroup>,
+    cache: &VideoHashFilesystemCache,
+    cache_path: &Path,
+    read_only: bool,
+) -> Vec<DupGroup> {
+    let has_audio = |src_path: &Path| {
+        cache
+            .fetch_stats(src_path)
+            .map(|stats| stats.has_audio())
+            .unwrap_or(false)
+    };
+
+    let audio_paths = groups
+        .iter()
+        .flat_map(group_paths)
+        .filter(|src_path| has_audio(src_path))
+        .map(Path::to_path_buf)
+        .collect::<HashSet<_>>();
+    let audio_hashes = audio_hash::audio_hashes(cache_path, audio_paths, read_only);
+
+    let audio_matches = |a: &Path, b: &Path| match (audio_hashes.get(a), audio_hashes.get(b)) {
+        (Some(a), Some(b)) => a.matches(b),
+        _ => true,
+    };
+
+    let mut affirmed = vec![];
+    for group in groups {
+        match group.reference() {
+            Some(reference) => {
+                let duplicates = group
+                    .duplicates()
+                    .filter(|dup| audio_matches(reference, dup))
+                    .map(Path::to_path_buf)
+                    .collect::<Vec<_>>();
+                if !duplicates.is_empty() {
+                    affirmed.push(DupGroup {
+                        reference: Some(reference.to_path_buf()),
+                        duplicates,
+                        exact: false,
+                    });
+                }
+            }
+            None => {
+                //Each member joins the first split whose first member has matching audio.
+                let mut splits: Vec<Vec<PathBuf>> = vec![];
+                for dup in group.duplicates() {
+                    match splits
+                        .iter_mut()
+                        .find(|split| audio_matches(&split[0], dup))
+                    {
+                        Some(split) => split.push(dup.to_path_buf()),
+                        None => splits.push(vec![dup.to_path_buf()]),
+                    }
+                }
+                affirmed.extend(splits.into_iter().filter(|split| split.len() >= 2).map(
+                    |duplicates| DupGroup {
+                        reference: None,
+                        duplicates,
+                        exact: false,
+                    },
+                ));
+            }
+        }
+    }
+
+    affirmed
+}
+
+//Sort unique files by the given key. Paths are sorted alphabetically, and everything else largest first,
+//with ties broken by path. Files whose stats are unavailable come last.
+pub fn sort_unique_paths(
+    unique_paths: &mut [&Path],
+    key: UniqueSortKey,
+    cache: &VideoHashFilesystemCache,
+) {
+    unique_paths.sort_by_cached_key(|unique_path| {
+        let value = cache.fetch_stats(unique_path).ok().map(|stats| match key {
+            UniqueSortKey::Path => 0,
+            UniqueSortKey::Size => stats.size(),
+            UniqueSortKey::Duration => (stats.duration() * 1000.0) as u64,
+            UniqueSortKey::Resolution => {
+                let (x, y) = stats.resolution();
+                u64::from(x) * u64::from(y)
+            }
+            UniqueSortKey::Bitrate => stats.bit_rate(),
+        });
+        (std::cmp::Reverse(value), unique_path.to_path_buf())
+    });
+}
+
+fn classify_group(group: &DupGroup, cache: &VideoHashFilesystemCache) -> GroupKind {
+    //Only read file contents when the hashes are identical, as hashing the contents of every file is slow.
+    if max_distance(group, cache) > 0.0 {
+        return GroupKind::NearDuplicate;
+    }
+
+    let digests = group_paths(group)
+        .map(|src_path| content_hash::sha256_file(src_path).ok())
+        .collect::<Vec<_>>();
+
+    if digests
+        .iter()
+        .all(|digest| digest.is_some() && *digest == digests[0])
+    {
+        GroupKind::Rename
+    } else {
+        GroupKind::NearDuplicate
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn group(paths: &[&str]) -> DupGroup {
+        DupGroup {
+            reference: None,
+            duplicates: paths.iter().map(PathBuf::from).collect(),
+            exact: false,
+        }
+    }
+
+    fn group_members(groups: &[DupGroup]) -> Vec<Vec<&Path>> {
+        groups
+            .iter()
+            .map(|group| group.duplicates().collect())
+            .collect()
+    }
+
+    #[test]
+    fn groups_sharing_a_member_are_merged() {
+        let merged = merge_transitive(vec![group(&["a", "b"]), group(&["b", "c"])]);
+
+        assert_eq!(
+            group_members(&merged),
+            vec![vec![Path::new("a"), Path::new("b"), Path::new("c")]]
+        );
+    }
+
+    #[test]
+    fn identical_members_survive_verification() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path_a = tempdir.path().join("a.mp4");
+        let path_a_copy = tempdir.path().join("a_copy.mp4");
+        let path_b = tempdir.path().join("b.mp4");
+        std::fs::write(&path_a, b"a").unwrap();
+        std::fs::write(&path_a_copy, b"a").unwrap();
+        std::fs::write(&path_b, b"b").unwrap();
+
+        let group = DupGroup {
+            reference: None,
+            duplicates: vec![path_a.clone(), path_b.clone(), path_a_copy.clone()],
+            exact: false,
+        };
+        let (split_groups, leftovers) = split_by_contents(&group);
+
+        assert_eq!(
+            group_members(&split_groups),
+            vec![vec![path_a.as_path(), path_a_copy.as_path()]]
+        );
+        assert_eq!(leftovers, vec![path_b]);
+    }
+
+    #[test]
+    fn unrelated_groups_stay_separate() {
+        let merged = merge_transitive(vec![
+            group(&["a", "b"]),
+            group(&["c", "d"]),
+            group(&["d", "e"]),
+        ]);
+
+        assert_eq!(
+            group_members(&merged),
+            vec![
+                vec![Path::new("a"), Path::new("b")],
+                vec![Path::new("c"), Path::new("d"), Path::new("e")]
+            ]
+        );
+    }
 }
@@ -0,0 +1,61 @@
+use std::{path::Path, process::Command};
+
+//Whether a video has any subtitle or attachment streams (attachments are usually fonts for subtitles).
+//VideoStats does not record this, so ffprobe is run separately.
+pub fn has_subtitles(src_path: &Path) -> bool {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "stream=codec_type",
+            "-of",
+            "json",
+        ])
+        .arg(src_path)
+        .output();
+
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            warn!(
+                "Failed to read subtitle streams of {}: ffprobe exited with {}",
+                src_path.display(),
+                output.status
+            );
+            return false;
+        }
+        Err(e) => {
+            warn!(
+                "Failed to read subtitle streams of {}: {}",
+                src_path.display(),
+                e
+            );
+            return false;
+        }
+    };
+
+    let probe: serde_json::Value = match serde_json::from_slice(&output.stdout) {
+        Ok(probe) => probe,
+        Err(e) => {
+            warn!(
+                "Failed to parse ffprobe output for {}: {}",
+                src_path.display(),
+                e
+            );
+            return false;
+        }
+    };
+
+    probe["streams"]
+        .as_array()
+        .map(|streams| {
+            streams.iter().any(|stream| {
+                matches!(
+                    stream["codec_type"].as_str(),
+                    Some("subtitle") | Some("attachment")
+                )
+            })
+        })
+        .unwrap_or(false)
+}
@@ -0,0 +1,45 @@
+use rand::seq::SliceRandom;
+use vid_dup_finder_lib::*;
+
+//Tolerances tried by --auto-tolerance. They cover the range of values suggested in the help text for --tolerance.
+const SWEEP_TOLERANCES: [f64; 5] = [0.02, 0.05, 0.1, 0.15, 0.2];
+
+//Searching is cheap compared to hashing, but the sweep runs the search once per tolerance, so on very large
+//libraries only a random sample of the candidates is searched.
+const SWEEP_MAX_SAMPLE: usize = 2000;
+
+pub fn print_tolerance_sweep(cand_hashes: Vec<VideoHash>, ref_hashes: Vec<VideoHash>) {
+    let num_cands = cand_hashes.len();
+    let sample = if num_cands > SWEEP_MAX_SAMPLE {
+        let mut rng = rand::thread_rng();
+        cand_hashes
+            .choose_multiple(&mut rng, SWEEP_MAX_SAMPLE)
+            .cloned()
+            .collect::<Vec<_>>()
+    } else {
+        cand_hashes
+    };
+
+    info!(
+        "Searching {} of {} candidates at {} different tolerances",
+        sample.len(),
+        num_cands,
+        SWEEP_TOLERANCES.len()
+    );
+
+    println!("{:>9} {:>7} {:>10}", "tolerance", "groups", "duplicates");
+    for &tol in SWEEP_TOLERANCES.iter() {
+        let tolerance = NormalizedTolerance::new(tol);
+        let match_set = if ref_hashes.is_empty() {
+            search(sample.clone(), tolerance)
+        } else {
+            search_with_references(ref_hashes.clone(), sample.clone(), tolerance)
+        };
+
+        let num_dups: usize = match_set
+            .iter()
+            .map(|group| group.duplicates().count())
+            .sum();
+        println!("{:>9} {:>7} {:>10}", tol, match_set.len(), num_dups);
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::{self, BufRead, Write};
+
+use crate::app::*;
+
+//Resolve each group of duplicates by typing the same resolutions as the GUI's keep command into the terminal.
+//Useful when there is no display to run the GUI on.
+pub fn run_tui(thunks: Vec<ResolutionThunk>) {
+    println!("For each group, enter the video to keep, using the same syntax as the GUI:");
+    println!("  \"1\"         keep video 1");
+    println!("  \"1 as 2\"    keep video 1, renamed to the name of video 2");
+    println!("  \"1 at 2\"    keep video 1, moved to the directory of video 2");
+    println!("  \"1 as 2 at 3\"");
+    println!("  \"l\" keeps the best video, an empty line skips the group, and \"q\" quits.");
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    let num_thunks = thunks.len();
+    for (i, thunk) in thunks.iter().enumerate() {
+        print_thunk(thunk, i, num_thunks);
+
+        loop {
+            print!("keep> ");
+            io::stdout().flush().unwrap_or_default();
+
+            //If stdin has been closed then no more resolutions can be read, so stop.
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => {
+                    println!();
+                    return;
+                }
+            };
+
+            let choice = match line.trim() {
+                "" => break,
+                "q" => return,
+                "l" => match thunk.best_entry_idx() {
+                    Some(idx) => idx.to_string(),
+                    None => {
+                        warn!("Could not choose a video to keep");
+                        continue;
+                    }
+                },
+                choice => choice.to_string(),
+            };
+
+            match thunk.resolve(&choice) {
+                Ok(()) => break,
+                Err(e) => warn!("{}", e),
+            }
+        }
+    }
+}
+
+fn print_thunk(thunk: &ResolutionThunk, i: usize, num_thunks: usize) {
+    println!();
+    match thunk.distance() {
+        Some(distance) => println!(
+            "Group {} of {} (distance {:.3})",
+            i + 1,
+            num_thunks,
+            distance
+        ),
+        None => println!("Group {} of {}", i + 1, num_thunks),
+    }
+
+    for (idx, src_path) in thunk.entries().into_iter().enumerate() {
+        let winning_stats = thunk.calc_winning_stats(src_path);
+        let labels = [
+            (winning_stats.is_reference, "REF"),
+            (winning_stats.pngsize, "PNG"),
+            (winning_stats.filesize, "FIL"),
+            (winning_stats.res, "RES"),
+            (winning_stats.bitrate, "BIT"),
+            (winning_stats.has_audio, "AUD"),
+            (winning_stats.has_subtitles, "SUB"),
+        ]
+        .iter()
+        .filter(|(won, _label)| *won)
+        .map(|(_won, label)| *label)
+        .collect::<Vec<_>>();
+
+        println!("[{}] {}", idx, src_path.display());
+        println!(
+            "    {}, {}, len: {}  {}",
+            thunk.render_details_top(src_path),
+            thunk.render_details_bottom(src_path),
+            thunk.render_duration(src_path),
+            labels.join(" ")
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::path::{Path, PathBuf};
+
+use video_hash_filesystem_cache::*;
+
+use crate::app::*;
+
+//Tolerances tried by --tune-tolerance, from 0.0 to 0.3 in steps of 0.01. This covers the range of values
+//suggested in the help text for --tolerance, with some room above it.
+const TUNE_STEP: f64 = 0.01;
+const TUNE_NUM_STEPS: u32 = 30;
+
+//Two files from the labels file of --tune-tolerance, and whether they are duplicates of each other.
+#[derive(Debug, Clone)]
+pub struct LabeledPair {
+    pub path_a: PathBuf,
+    pub path_b: PathBuf,
+    pub is_dup: bool,
+}
+
+//Read the labels file. Each line holds two paths and a label separated by commas, where the label is "dup" (or 1)
+//for duplicates and "not" (or 0) for files that are not duplicates. Paths must not contain commas. Relative
+//paths are relative to the directory of the labels file. Blank lines and lines starting with '#' are ignored.
+pub fn load_labels(labels_path: &Path) -> Result<Vec<LabeledPair>, AppError> {
+    let labels_text = std::fs::read_to_string(labels_path)
+        .map_err(|e| AppError::LabelsFileReadError(labels_path.to_path_buf(), e))?;
+    let labels_dir = labels_path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut pairs = vec![];
+    for (line_idx, line) in labels_text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parse_err =
+            || AppError::LabelsFileParseError(labels_path.to_path_buf(), line_idx + 1, line.into());
+        let (path_a, path_b, label) = match line.split(',').map(str::trim).collect::<Vec<_>>()[..] {
+            [path_a, path_b, label] => (path_a, path_b, label),
+            _ => return Err(parse_err()),
+        };
+        let is_dup = match label.to_lowercase().as_str() {
+            "dup" | "1" => true,
+            "not" | "0" => false,
+            _ => return Err(parse_err()),
+        };
+
+        //Paths are canonicalized to match the paths in the cache.
+        let resolve_path = |path: &str| {
+            let path = labels_dir.join(path);
+            path.canonicalize().unwrap_or(path)
+        };
+        pairs.push(LabeledPair {
+            path_a: resolve_path(path_a),
+            path_b: resolve_path(path_b),
+            is_dup,
+        });
+    }
+
+    Ok(pairs)
+}
+
+//Print the precision, recall and F1 score of matching the labeled pairs at a range of tolerances, followed by the
+//tolerance with the best F1 score. A pair matches at a tolerance if the distance between its hashes is no larger
+//than the tolerance. Pairs where either file has no hash are skipped.
+pub fn print_tolerance_tuning(pairs: &[LabeledPair], cache: &VideoHashFilesystemCache) {
+    let distances = pairs
+        .iter()
+        .filter_map(
+            |pair| match (cache.fetch(&pair.path_a), cache.fetch(&pair.path_b)) {
+                (Ok(hash_a), Ok(hash_b)) => Some((
+                    hash_a.normalized_levenshtein_distance(&hash_b).value(),
+                    pair.is_dup,
+                )),
+                _ => {
+                    warn!(
+                        "Skipping labeled pair with no hash: {} and {}",
+                        pair.path_a.display(),
+                        pair.path_b.display()
+                    );
+                    None
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+
+    let num_dups = distances
+        .iter()
+        .filter(|(_distance, is_dup)| *is_dup)
+        .count();
+    info!(
+        "Tuning tolerance on {} labeled pairs ({} duplicates)",
+        distances.len(),
+        num_dups
+    );
+
+    println!(
+        "{:>9} {:>9} {:>6} {:>6}",
+        "tolerance", "precision", "recall", "f1"
+    );
+
+    let mut best: Option<(f64, f64)> = None;
+    for step in 0..=TUNE_NUM_STEPS {
+        let tolerance = step as f64 * TUNE_STEP;
+
+        let (mut true_pos, mut false_pos) = (0, 0);
+        for (distance, is_dup) in &distances {
+            if *distance <= tolerance {
+                if *is_dup {
+                    true_pos += 1;
+                } else {
+                    false_pos += 1;
+                }
+            }
+        }
+
+        let precision = ratio(true_pos, true_pos + false_pos);
+        let recall = ratio(true_pos, num_dups);
+        let f1 = match (precision, recall) {
+            (Some(precision), Some(recall)) if precision + recall > 0.0 => {
+                Some(2.0 * precision * recall / (precision + recall))
+            }
+            _ => None,
+        };
+
+        println!(
+            "{:>9.2} {:>9} {:>6} {:>6}",
+            tolerance,
+            render_ratio(precision),
+            render_ratio(recall),
+            render_ratio(f1)
+        );
+
+        //On ties keep the lowest tolerance, as it gives the fewest false matches on unlabeled videos.
+        if let Some(f1) = f1 {
+            let is_best = match best {
+                Some((_tolerance, best_f1)) => f1 > best_f1,
+                None => true,
+            };
+            if is_best {
+                best = Some((tolerance, f1));
+            }
+        }
+    }
+
+    match best {
+        Some((tolerance, _f1)) => println!("Suggested --tolerance: {:.2}", tolerance),
+        None => println!("No tolerance matched any labeled duplicates, so none can be suggested"),
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> Option<f64> {
+    match denominator {
+        0 => None,
+        _ => Some(numerator as f64 / denominator as f64),
+    }
+}
+
+fn render_ratio(ratio: Option<f64>) -> String {
+    ratio.map_or_else(|| "-".to_string(), |ratio| format!("{:.3}", ratio))
+}
@@ -4,7 +4,6 @@
 #[macro_use]
 extern crate log;
 
-#[cfg(all(target_family = "unix", feature = "gui"))]
 extern crate lazy_static;
 
 mod app;